@@ -0,0 +1,12 @@
+#version 300 es
+precision highp float;
+
+uniform sampler2D image;
+
+in vec2 vUv;
+out vec4 frag_color;
+
+void main()
+{
+  frag_color = texture( image, vUv );
+}
@@ -0,0 +1,212 @@
+//! Adaptive resolution, which lowers render size of heavy shaders to hold a target frame rate.
+//!
+//! Image is rendered into a scaled framebuffer and stretched to the canvas, so size of
+//! the canvas and its layout are not affected by adjustments.
+
+use crate::{
+    program::{compile_program, ProgramError},
+    state::Render,
+};
+use web_sys::{
+    WebGl2RenderingContext as GL, WebGlFramebuffer, WebGlProgram, WebGlTexture,
+    WebGlUniformLocation,
+};
+
+const UPSCALE_FRAGMENT_SHADER_SRC: &str = include_str!("../shaders/upscale.frag");
+/// Time of frame rate measurement between adjustments, in seconds
+const MEASURE_INTERVAL: f64 = 0.5;
+/// Scale is lowered if frame rate is below this part of the target
+const LOWER_THRESHOLD: f64 = 0.9;
+/// Scale is raised if frame rate reaches this part of the target
+const RAISE_THRESHOLD: f64 = 0.98;
+/// Multiplier of scale for raise, small to approach the limit of the device slowly
+const RAISE_STEP: f32 = 1.1;
+/// Scale is rounded to this step, so buffers are not recreated for tiny changes
+const SCALE_STEP: f32 = 0.05;
+const DEFAULT_MIN_SCALE: f32 = 0.25;
+
+/// Bounds and target of the controller, set with `render` section of player state.
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveOptions {
+    target_fps: f64,
+    min_scale: f32,
+    max_scale: f32,
+}
+
+impl AdaptiveOptions {
+    /// Returns `None` if adaptive resolution is disabled, which is the default.
+    pub fn from_render(render: Option<Render>) -> Option<Self> {
+        let render = render?;
+        let target_fps = render
+            .target_fps
+            .filter(|fps| fps.is_finite() && *fps > 0.0)?;
+        let valid = |scale: &f32| scale.is_finite() && *scale > 0.0;
+        let max_scale = render.max_scale.filter(valid).unwrap_or(1.0).min(1.0);
+        let min_scale = render
+            .min_scale
+            .filter(valid)
+            .unwrap_or(DEFAULT_MIN_SCALE)
+            .min(max_scale);
+        Some(Self {
+            target_fps: f64::from(target_fps),
+            min_scale,
+            max_scale,
+        })
+    }
+}
+
+/// Measures frame rate and picks scale of render size relative to the drawing buffer.
+#[derive(Debug)]
+pub struct ResolutionController {
+    scale: f32,
+    /// Time and number of frames since the last adjustment
+    elapsed: f64,
+    frames: u32,
+}
+
+impl Default for ResolutionController {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            elapsed: 0.0,
+            frames: 0,
+        }
+    }
+}
+
+impl ResolutionController {
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Accounts a frame which took `frame_time` seconds, scale is adjusted once per measure interval.
+    pub fn update(&mut self, frame_time: f64, options: AdaptiveOptions) {
+        self.elapsed += frame_time;
+        self.frames += 1;
+        if self.elapsed < MEASURE_INTERVAL {
+            return;
+        }
+
+        let fps = f64::from(self.frames) / self.elapsed;
+        self.elapsed = 0.0;
+        self.frames = 0;
+        let scale = if fps < options.target_fps * LOWER_THRESHOLD {
+            // Number of rendered pixels is proportional to square of scale
+            self.scale * (fps / options.target_fps).sqrt() as f32
+        } else if fps >= options.target_fps * RAISE_THRESHOLD {
+            self.scale * RAISE_STEP
+        } else {
+            self.scale
+        };
+        self.scale =
+            ((scale / SCALE_STEP).round() * SCALE_STEP).clamp(options.min_scale, options.max_scale);
+    }
+
+    /// Size of rendered image for the drawing buffer of `size`.
+    pub fn render_size(&self, (width, height): (i32, i32)) -> (i32, i32) {
+        let scale = |length: i32| ((length as f32 * self.scale).round() as i32).max(1);
+        (scale(width), scale(height))
+    }
+}
+
+/// Framebuffer with rendered image of reduced size.
+struct ScaledTarget {
+    texture: WebGlTexture,
+    framebuffer: WebGlFramebuffer,
+    size: (i32, i32),
+}
+
+impl ScaledTarget {
+    /// Returns `None` if context is lost.
+    fn new(gl: &GL, (width, height): (i32, i32)) -> Option<Self> {
+        let texture = gl.create_texture()?;
+        let framebuffer = gl.create_framebuffer()?;
+        gl.bind_texture(GL::TEXTURE_2D, Some(&texture));
+        gl.tex_storage_2d(GL::TEXTURE_2D, 1, GL::RGBA8, width, height);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+        gl.bind_texture(GL::TEXTURE_2D, None);
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&framebuffer));
+        gl.framebuffer_texture_2d(
+            GL::FRAMEBUFFER,
+            GL::COLOR_ATTACHMENT0,
+            GL::TEXTURE_2D,
+            Some(&texture),
+            0,
+        );
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+        Some(Self {
+            texture,
+            framebuffer,
+            size: (width, height),
+        })
+    }
+
+    fn delete(&self, gl: &GL) {
+        gl.delete_framebuffer(Some(&self.framebuffer));
+        gl.delete_texture(Some(&self.texture));
+    }
+}
+
+/// Stretches image of reduced size to the canvas.
+///
+/// Blit is not used, because it fails for multisampled drawing buffer of antialiased context.
+pub struct Upscaler {
+    program: WebGlProgram,
+    image: Option<WebGlUniformLocation>,
+    target: Option<ScaledTarget>,
+}
+
+impl Upscaler {
+    pub fn new(gl: &GL, vertex_shader_src: &str) -> Result<Self, ProgramError> {
+        let program = compile_program(gl, vertex_shader_src, UPSCALE_FRAGMENT_SHADER_SRC)?;
+        let image = gl.get_uniform_location(&program, "image");
+        Ok(Self {
+            program,
+            image,
+            target: None,
+        })
+    }
+
+    /// Framebuffer of `size` for the image, recreated when size changes.
+    pub fn target(&mut self, gl: &GL, size: (i32, i32)) -> Option<WebGlFramebuffer> {
+        if self
+            .target
+            .as_ref()
+            .is_some_and(|target| target.size != size)
+        {
+            if let Some(target) = self.target.take() {
+                target.delete(gl);
+            }
+        }
+        if self.target.is_none() {
+            self.target = ScaledTarget::new(gl, size);
+        }
+        self.target
+            .as_ref()
+            .map(|target| target.framebuffer.clone())
+    }
+
+    /// Draws the image rendered into `target()` over the whole canvas.
+    pub fn present(&self, gl: &GL, (width, height): (i32, i32)) {
+        let Some(target) = &self.target else {
+            return;
+        };
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+        gl.viewport(0, 0, width, height);
+        gl.use_program(Some(&self.program));
+        gl.active_texture(GL::TEXTURE0);
+        gl.bind_texture(GL::TEXTURE_2D, Some(&target.texture));
+        gl.uniform1i(self.image.as_ref(), 0);
+        gl.draw_arrays(GL::TRIANGLE_STRIP, 0, 4);
+    }
+
+    pub fn delete(self, gl: &GL) {
+        if let Some(target) = &self.target {
+            target.delete(gl);
+        }
+        gl.delete_program(Some(&self.program));
+    }
+}
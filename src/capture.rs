@@ -0,0 +1,289 @@
+//! Offscreen rendering of frames, which are read back and encoded for export.
+
+use crate::{
+    pipeline::{BufferTargets, FrameUniforms, Pipeline},
+    texture::ChannelTextures,
+};
+use serde::Deserialize;
+use web_sys::{WebGl2RenderingContext as GL, WebGlFramebuffer, WebGlTexture};
+
+/// Options of `screenshot()`, missing values are taken from the last frame on the canvas.
+#[derive(Clone, Copy, Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct CaptureOptions {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub time: Option<f32>,
+    pub frame: Option<i32>,
+    /// Frames rendered before the captured one, so feedback buffers evolve; 0 by default
+    pub warmup_frames: Option<u32>,
+}
+
+/// Content of `data` passed to the callback of `render_frames()`.
+#[derive(Clone, Copy, Debug, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameFormat {
+    /// Raw RGBA pixels, top row first
+    #[default]
+    Rgba,
+    /// PNG file
+    Png,
+}
+
+/// Options of `render_frames()`, only `duration` is required.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct RecordingOptions {
+    #[serde(default = "RecordingOptions::default_fps")]
+    pub fps: f64,
+    /// Length of the sequence in seconds
+    pub duration: f64,
+    /// `iTime` of the first frame
+    #[serde(default)]
+    pub start_time: f64,
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(default)]
+    pub height: Option<u32>,
+    #[serde(default)]
+    pub format: FrameFormat,
+}
+
+/// Uniforms of a frame of `render_frames()`, which depend only on its index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameTiming {
+    pub time: f64,
+    pub time_delta: f64,
+    pub frame: i32,
+}
+
+impl RecordingOptions {
+    fn default_fps() -> f64 {
+        60.0
+    }
+
+    /// Timing of frame `index`, time steps by exactly `1 / fps`.
+    ///
+    /// `iFrame` counts from 0 whatever `start_time` is: buffers of a recording start empty,
+    /// so shaders which fill them on `iFrame == 0` work from any start time.
+    pub fn frame_timing(&self, index: u32) -> FrameTiming {
+        let time_delta = 1.0 / self.fps;
+        FrameTiming {
+            time: self.start_time + f64::from(index) * time_delta,
+            time_delta,
+            frame: index as i32,
+        }
+    }
+
+    /// Number of frames to render, checks that options make sense.
+    pub fn frame_count(&self) -> Result<u32, String> {
+        if !(self.fps.is_finite() && self.fps > 0.0) {
+            return Err(format!("Frame rate must be positive, got {}", self.fps));
+        }
+        if !(self.duration.is_finite() && self.duration >= 0.0) {
+            return Err(format!(
+                "Duration must be non-negative, got {}",
+                self.duration
+            ));
+        }
+        Ok((self.duration * self.fps).round() as u32)
+    }
+}
+
+/// Frames which are rendered for a capture, content of the last one is read back.
+pub enum CaptureFrames<'a> {
+    /// Image pass over buffers shown on the canvas, so feedback shaders are captured as they are
+    Live(&'a FrameUniforms),
+    /// All passes of each frame in order, buffers evolve as they would on the canvas
+    Sequence(&'a [FrameUniforms]),
+}
+
+/// 8-bit color target of arbitrary size with its own buffers, independent of the canvas.
+pub struct Offscreen {
+    texture: WebGlTexture,
+    framebuffer: WebGlFramebuffer,
+    targets: BufferTargets,
+    width: i32,
+    height: i32,
+}
+
+impl Offscreen {
+    pub fn new(gl: &GL, width: u32, height: u32) -> Result<Self, String> {
+        let max_size = gl
+            .get_parameter(GL::MAX_TEXTURE_SIZE)
+            .ok()
+            .and_then(|size| size.as_f64())
+            .unwrap_or(0.0) as u32;
+        if width == 0 || height == 0 || width > max_size || height > max_size {
+            return Err(format!(
+                "Capture size {width}x{height} is not supported, maximum is {max_size}x{max_size}"
+            ));
+        }
+        let (width, height) = (width as i32, height as i32);
+
+        let texture = gl
+            .create_texture()
+            .ok_or("Failed to create capture texture")?;
+        let Some(framebuffer) = gl.create_framebuffer() else {
+            gl.delete_texture(Some(&texture));
+            return Err("Failed to create capture framebuffer".to_owned());
+        };
+        gl.bind_texture(GL::TEXTURE_2D, Some(&texture));
+        gl.tex_storage_2d(GL::TEXTURE_2D, 1, GL::RGBA8, width, height);
+        gl.bind_texture(GL::TEXTURE_2D, None);
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&framebuffer));
+        gl.framebuffer_texture_2d(
+            GL::FRAMEBUFFER,
+            GL::COLOR_ATTACHMENT0,
+            GL::TEXTURE_2D,
+            Some(&texture),
+            0,
+        );
+        let status = gl.check_framebuffer_status(GL::FRAMEBUFFER);
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+
+        let offscreen = Self {
+            texture,
+            framebuffer,
+            targets: BufferTargets::default(),
+            width,
+            height,
+        };
+        if status != GL::FRAMEBUFFER_COMPLETE {
+            offscreen.delete(gl);
+            return Err(format!("Capture framebuffer is incomplete: {status:#x}"));
+        }
+        Ok(offscreen)
+    }
+
+    /// Renders a frame, buffers keep their content between calls as on the canvas.
+    pub fn draw(
+        &mut self,
+        gl: &GL,
+        pipeline: &Pipeline,
+        uniforms: &FrameUniforms,
+        textures: &ChannelTextures,
+    ) {
+        pipeline.draw_offscreen(
+            gl,
+            uniforms,
+            textures,
+            &mut self.targets,
+            &self.framebuffer,
+            (self.width, self.height),
+        );
+    }
+
+    /// Renders the image pass over buffers shown on the canvas, see `Pipeline::draw_image_offscreen()`.
+    pub fn draw_image(
+        &self,
+        gl: &GL,
+        pipeline: &Pipeline,
+        uniforms: &FrameUniforms,
+        textures: &ChannelTextures,
+    ) -> bool {
+        pipeline.draw_image_offscreen(
+            gl,
+            uniforms,
+            textures,
+            &self.framebuffer,
+            (self.width, self.height),
+        )
+    }
+
+    /// RGBA pixels of the last frame, top row first.
+    pub fn read_pixels(&self, gl: &GL) -> Result<Vec<u8>, String> {
+        let row = self.width as usize * 4;
+        let mut pixels = vec![0u8; row * self.height as usize];
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&self.framebuffer));
+        let result = gl.read_pixels_with_opt_u8_array(
+            0,
+            0,
+            self.width,
+            self.height,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            Some(&mut pixels),
+        );
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+        result.map_err(|error| format!("Failed to read pixels: {error:?}"))?;
+
+        // GL rows go from the bottom up
+        let rows = pixels.chunks_exact(row).rev().flatten().copied().collect();
+        Ok(rows)
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width as u32, self.height as u32)
+    }
+
+    pub fn delete(mut self, gl: &GL) {
+        self.targets.delete(gl);
+        gl.delete_framebuffer(Some(&self.framebuffer));
+        gl.delete_texture(Some(&self.texture));
+    }
+}
+
+/// Encodes RGBA pixels, top row first, into a PNG file.
+pub fn encode_png(width: u32, height: u32, pixels: &[u8]) -> Result<Vec<u8>, String> {
+    let mut file = Vec::new();
+    let mut encoder = png::Encoder::new(&mut file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| {
+            writer.write_image_data(pixels)?;
+            writer.finish()
+        })
+        .map_err(|error| format!("Failed to encode PNG: {error}"))?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(json: &str) -> RecordingOptions {
+        serde_json::from_str(json).expect("valid options")
+    }
+
+    #[test]
+    fn frame_count() {
+        assert_eq!(options(r#"{ "duration": 2 }"#).frame_count(), Ok(120));
+        assert_eq!(
+            options(r#"{ "duration": 1, "fps": 24 }"#).frame_count(),
+            Ok(24)
+        );
+        assert!(options(r#"{ "duration": 1, "fps": 0 }"#)
+            .frame_count()
+            .is_err());
+        assert!(options(r#"{ "duration": -1 }"#).frame_count().is_err());
+    }
+
+    #[test]
+    fn frames_step_by_exact_intervals() {
+        let options = options(r#"{ "duration": 1, "fps": 30 }"#);
+        assert_eq!(
+            options.frame_timing(0),
+            FrameTiming {
+                time: 0.0,
+                time_delta: 1.0 / 30.0,
+                frame: 0
+            }
+        );
+        let middle = options.frame_timing(15);
+        assert!((middle.time - 0.5).abs() < 1e-12, "{middle:?}");
+        assert_eq!(middle.frame, 15);
+    }
+
+    #[test]
+    fn frame_counts_from_zero_after_start_time() {
+        // Buffers of a recording start empty, so the first frame is `iFrame == 0` at any time
+        let options = options(r#"{ "duration": 1, "start_time": 10 }"#);
+        let first = options.frame_timing(0);
+        assert_eq!((first.time, first.frame), (10.0, 0));
+        let next = options.frame_timing(60);
+        assert!((next.time - 11.0).abs() < 1e-12, "{next:?}");
+        assert_eq!(next.frame, 60);
+    }
+}
@@ -0,0 +1,354 @@
+//! Errors thrown to JS and reported through `WasmErrorEvent`.
+
+use crate::{
+    pipeline::CompileError,
+    program::ProgramStage,
+    shader::{SourceMap, SourceOrigin},
+};
+use core::fmt;
+use serde::Serialize;
+use wasm_bindgen::JsValue;
+
+/// Code of an error, `kind` of `WasmErrorEvent` and `code` of thrown errors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// WebGL2 context can't be created or is lost
+    Context,
+    /// Shader compilation, `line` points to the failed line
+    Compile,
+    /// Program linking, usually without `line`
+    Link,
+    /// Value passed from JS has unknown format or doesn't make sense
+    InvalidArgument,
+    /// Player is used by an operation in progress
+    Busy,
+    /// Default player wasn't created
+    NotInitialized,
+    /// Operation was superseded by a later call, nothing failed
+    Cancelled,
+    /// Everything else: failed browser and GL calls
+    Runtime,
+}
+
+impl ErrorKind {
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorKind::Context => "context",
+            ErrorKind::Compile => "compile",
+            ErrorKind::Link => "link",
+            ErrorKind::InvalidArgument => "invalid_argument",
+            ErrorKind::Busy => "busy",
+            ErrorKind::NotInitialized => "not_initialized",
+            ErrorKind::Cancelled => "cancelled",
+            ErrorKind::Runtime => "runtime",
+        }
+    }
+}
+
+/// Failure of a player operation.
+#[derive(Debug)]
+pub enum PlayerError {
+    /// WebGL2 context can't be created or is lost
+    Context(String),
+    /// Shader of a pass failed to compile or link
+    Compile(CompileError),
+    /// Project, state or options from JS can't be used
+    InvalidArgument(String),
+    /// Part of the player, which is borrowed by a running operation
+    Busy(&'static str),
+    NotInitialized,
+    Cancelled(&'static str),
+    Runtime(String),
+}
+
+impl PlayerError {
+    /// Argument from JS, which failed to deserialize into `what`.
+    pub fn invalid_format(what: &str, error: impl fmt::Display) -> Self {
+        PlayerError::InvalidArgument(format!("Unknown {what} format: {error}"))
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            PlayerError::Context(_) => ErrorKind::Context,
+            PlayerError::Compile(error) if error.error.stage == ProgramStage::Link => {
+                ErrorKind::Link
+            }
+            PlayerError::Compile(_) => ErrorKind::Compile,
+            PlayerError::InvalidArgument(_) => ErrorKind::InvalidArgument,
+            PlayerError::Busy(_) => ErrorKind::Busy,
+            PlayerError::NotInitialized => ErrorKind::NotInitialized,
+            PlayerError::Cancelled(_) => ErrorKind::Cancelled,
+            PlayerError::Runtime(_) => ErrorKind::Runtime,
+        }
+    }
+
+    /// Details for `WasmErrorEvent`, compilation results in one detail per error of the info log.
+    pub fn details(&self) -> Vec<ErrorDetail> {
+        match self {
+            PlayerError::Compile(error) => error.details(),
+            _ => vec![ErrorDetail::new(self.kind(), self.to_string())],
+        }
+    }
+}
+
+impl fmt::Display for PlayerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayerError::Context(message) => write!(f, "WebGL2 context error: {message}"),
+            PlayerError::Compile(error) => write!(f, "{error}"),
+            PlayerError::InvalidArgument(message) | PlayerError::Runtime(message) => {
+                write!(f, "{message}")
+            }
+            PlayerError::Busy(part) => write!(f, "Player {part} is busy, try again later"),
+            PlayerError::NotInitialized => write!(f, "Default player is not initialized"),
+            PlayerError::Cancelled(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+/// JS `Error` with `code` from `ErrorKind` and `details` as in `WasmErrorEvent`.
+impl From<PlayerError> for JsValue {
+    fn from(error: PlayerError) -> Self {
+        let js_error = js_sys::Error::new(&error.to_string());
+        let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+        let details = error
+            .details()
+            .serialize(&serializer)
+            .unwrap_or(JsValue::NULL);
+        let fields = [
+            ("code", JsValue::from_str(error.kind().code())),
+            ("details", details),
+        ];
+        for (key, value) in fields {
+            let _ = js_sys::Reflect::set(&js_error, &JsValue::from_str(key), &value);
+        }
+        js_error.into()
+    }
+}
+
+/// Detail of `WasmErrorEvent`.
+#[derive(Clone, Debug, Serialize)]
+pub struct ErrorDetail {
+    pub kind: ErrorKind,
+    /// Failed pass: `Image` or `Buffer A`..`Buffer D`
+    pub pass: Option<String>,
+    /// Part of the pass code, which `line` belongs to
+    pub source: Option<SourceOrigin>,
+    /// Name of included module, if `source` is `Module`
+    pub module: Option<String>,
+    /// 1-based line within `source`
+    pub line: Option<u32>,
+    /// 1-based column, only some drivers report it
+    pub column: Option<u32>,
+    pub message: String,
+}
+
+impl ErrorDetail {
+    /// Error, which is not related to shader code.
+    pub fn new(kind: ErrorKind, message: String) -> Self {
+        Self {
+            kind,
+            pass: None,
+            source: None,
+            module: None,
+            line: None,
+            column: None,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ErrorDetail {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(pass) = &self.pass {
+            write!(f, "{pass}: ")?;
+        }
+        if let Some(line) = self.line {
+            match (&self.module, self.source) {
+                (Some(module), _) => write!(f, "{module}:")?,
+                (None, Some(source)) => write!(f, "{source:?}:")?,
+                (None, None) => {}
+            }
+            write!(f, "{line}:")?;
+            if let Some(column) = self.column {
+                write!(f, "{column}:")?;
+            }
+            write!(f, " ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Located diagnostic of GLSL info log, line is in prepared source.
+struct LogEntry<'a> {
+    line: u32,
+    column: Option<u32>,
+    message: &'a str,
+}
+
+/// Parses `ERROR: 0:12: message` (ANGLE) and `0:12(5): error: message` (Mesa) formats.
+fn parse_log_line(text: &str) -> Option<LogEntry<'_>> {
+    let text = text.trim_start();
+    let text = text.strip_prefix("ERROR:").unwrap_or(text).trim_start();
+
+    let (source_string, rest) = text.split_once(':')?;
+    source_string.parse::<u32>().ok()?;
+    let line_end = rest.find(|c: char| !c.is_ascii_digit())?;
+    let line = rest[..line_end].parse().ok()?;
+    let rest = &rest[line_end..];
+    let (column, rest) = match rest.strip_prefix('(') {
+        Some(rest) => {
+            let (column, rest) = rest.split_once(')')?;
+            (column.parse().ok(), rest)
+        }
+        None => (None, rest),
+    };
+
+    let message = rest.strip_prefix(':')?.trim();
+    if message.starts_with("warning:") {
+        return None;
+    }
+    let message = message.strip_prefix("error:").unwrap_or(message).trim();
+
+    Some(LogEntry {
+        line,
+        column,
+        message,
+    })
+}
+
+/// Splits info log of a failed pass into errors with lines of the code they came from.
+///
+/// Log without recognizable locations results in a single error with the whole log.
+pub fn info_log_details(
+    pass: &str,
+    kind: ErrorKind,
+    log: &str,
+    source_map: Option<&SourceMap>,
+) -> Vec<ErrorDetail> {
+    let details: Vec<_> = log
+        .lines()
+        .filter_map(parse_log_line)
+        .map(|entry| {
+            let location = source_map.and_then(|map| map.map(entry.line));
+            ErrorDetail {
+                kind,
+                pass: Some(pass.to_owned()),
+                source: location.map(|location| location.origin),
+                module: location
+                    .and_then(|location| location.module)
+                    .map(str::to_owned),
+                line: Some(location.map_or(entry.line, |location| location.line)),
+                column: entry.column,
+                message: entry.message.to_owned(),
+            }
+        })
+        .collect();
+
+    if details.is_empty() {
+        vec![ErrorDetail {
+            kind,
+            pass: Some(pass.to_owned()),
+            source: None,
+            module: None,
+            line: None,
+            column: None,
+            message: log.trim().to_owned(),
+        }]
+    } else {
+        details
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        include::{register_module, with_library},
+        shader::{prepare_shader, PassKind},
+    };
+
+    /// 1-based line of prepared source, which contains `text`.
+    fn line_of(source: &str, text: &str) -> u32 {
+        let index = source.lines().position(|line| line.contains(text));
+        index.expect("line is in the source") as u32 + 1
+    }
+
+    #[test]
+    fn parses_angle_log_line() {
+        let entry = parse_log_line("ERROR: 0:57: 'bad' : undeclared identifier").unwrap();
+        assert_eq!(entry.line, 57);
+        assert_eq!(entry.column, None);
+        assert_eq!(entry.message, "'bad' : undeclared identifier");
+    }
+
+    #[test]
+    fn parses_mesa_log_line() {
+        let entry = parse_log_line("0:12(5): error: `bad' undeclared").unwrap();
+        assert_eq!(entry.line, 12);
+        assert_eq!(entry.column, Some(5));
+        assert_eq!(entry.message, "`bad' undeclared");
+    }
+
+    #[test]
+    fn skips_warnings_and_summaries() {
+        assert!(parse_log_line("0:3(1): warning: unused variable").is_none());
+        assert!(parse_log_line("ERROR: 2 compilation errors.  No code generated.").is_none());
+        assert!(parse_log_line("").is_none());
+    }
+
+    #[test]
+    fn log_without_locations_is_one_error() {
+        let details = info_log_details("Image", ErrorKind::Link, "  Link failed\n", None);
+        assert_eq!(details.len(), 1);
+        assert_eq!(details[0].pass.as_deref(), Some("Image"));
+        assert_eq!(details[0].line, None);
+        assert_eq!(details[0].message, "Link failed");
+    }
+
+    #[test]
+    fn lines_are_mapped_through_includes() {
+        register_module("noise", "float noise(vec2 p) {\n    return wrong;\n}").unwrap();
+        let common = "float scale = 2.0;\nfloat broken_common;";
+        let code = "#include \"noise\"\n\
+            void mainImage(out vec4 color, in vec2 coord) {\n    \
+                color = vec4(noise(coord));\n    \
+                broken_user;\n\
+            }";
+        let shader = with_library(|library| prepare_shader(common, code, PassKind::Image, library));
+        let log = format!(
+            "ERROR: 0:{}: 'wrong' : undeclared identifier\n\
+             0:{}(5): error: `broken_user' undeclared\n\
+             ERROR: 0:{}: 'broken_common' : redefinition\n\
+             ERROR: 0:1: '' : generated",
+            line_of(&shader.source, "return wrong"),
+            line_of(&shader.source, "broken_user"),
+            line_of(&shader.source, "broken_common"),
+        );
+
+        let details = info_log_details(
+            "Buffer A",
+            ErrorKind::Compile,
+            &log,
+            Some(&shader.source_map),
+        );
+        let locations: Vec<_> = details
+            .iter()
+            .map(|detail| (detail.source, detail.module.as_deref(), detail.line))
+            .collect();
+        assert_eq!(
+            locations,
+            [
+                (Some(SourceOrigin::Module), Some("noise"), Some(2)),
+                (Some(SourceOrigin::User), None, Some(4)),
+                (Some(SourceOrigin::Common), None, Some(2)),
+                (Some(SourceOrigin::Generated), None, Some(1)),
+            ]
+        );
+        assert_eq!(details[1].column, Some(5));
+        assert!(details
+            .iter()
+            .all(|detail| detail.pass.as_deref() == Some("Buffer A")));
+    }
+}
@@ -0,0 +1,94 @@
+//! Lifecycle notifications of a player, dispatched as `CustomEvent` on its canvas.
+
+use crate::{
+    dispatch_event,
+    error::ErrorDetail,
+    params::ShaderParam,
+    state::{PlaybackMode, PlayerState},
+    stats::FrameStats,
+};
+use serde::Serialize;
+use web_sys::EventTarget;
+
+/// Active uniforms of a linked pass.
+#[derive(Clone, Debug, Serialize)]
+pub struct PassUniforms {
+    /// `Image` or `Buffer A`..`Buffer D`
+    pub pass: String,
+    /// Names as reported by the driver, elements of arrays are named like `iTouch[0]`
+    pub uniforms: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ShaderCompiled {
+    /// Time of compilation and linking of all passes
+    pub compile_time_ms: f64,
+    pub passes: Vec<PassUniforms>,
+    /// Custom uniforms declared by the shader, see `get_shader_params()`
+    pub params: Vec<ShaderParam>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ShaderCompileFailed {
+    /// The same errors are reported with `WasmErrorEvent`
+    pub errors: Vec<ErrorDetail>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+pub struct PlaybackChanged {
+    pub paused: bool,
+    pub speed: f32,
+    pub mode: PlaybackMode,
+    /// Target of `seek()` or `restart()`, `None` for other changes
+    pub seek: Option<f64>,
+}
+
+impl PlaybackChanged {
+    pub fn new(state: &PlayerState, seek: Option<f64>) -> Self {
+        Self {
+            paused: state.paused(),
+            speed: state.speed(),
+            mode: state.playback_mode(),
+            seek,
+        }
+    }
+}
+
+/// Uniforms of a frame drawn on the canvas.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct FrameRendered {
+    pub frame: i32,
+    pub time: f32,
+    pub time_delta: f32,
+}
+
+/// Event with its detail, variants serialize as their detail.
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum PlayerEvent {
+    ShaderCompiled(ShaderCompiled),
+    ShaderCompileFailed(ShaderCompileFailed),
+    ContextLost,
+    ContextRestored,
+    PlaybackChanged(PlaybackChanged),
+    FrameRendered(FrameRendered),
+    FrameStats(FrameStats),
+}
+
+impl PlayerEvent {
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            PlayerEvent::ShaderCompiled(_) => "ShaderCompiled",
+            PlayerEvent::ShaderCompileFailed(_) => "ShaderCompileFailed",
+            PlayerEvent::ContextLost => "ContextLost",
+            PlayerEvent::ContextRestored => "ContextRestored",
+            PlayerEvent::PlaybackChanged(_) => "PlaybackChanged",
+            PlayerEvent::FrameRendered(_) => "FrameRendered",
+            PlayerEvent::FrameStats(_) => "FrameStatsEvent",
+        }
+    }
+
+    pub fn dispatch(&self, target: &EventTarget) {
+        dispatch_event(target, self.event_type(), self);
+    }
+}
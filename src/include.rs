@@ -0,0 +1,269 @@
+//! `#include "name"` directives, which pull GLSL modules registered from JS into shader code.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+};
+
+thread_local! {
+    // Modules are shared by all players of the page
+    static LIBRARY: RefCell<ShaderLibrary> = RefCell::default();
+}
+
+/// GLSL modules by name, registered with `register_shader_module()`.
+#[derive(Default)]
+pub struct ShaderLibrary {
+    modules: BTreeMap<String, String>,
+}
+
+impl ShaderLibrary {
+    /// Adds or replaces a module, name is checked by `register_module()`.
+    pub fn insert(&mut self, name: &str, source: &str) {
+        self.modules.insert(name.to_owned(), source.to_owned());
+    }
+
+    fn get(&self, name: &str) -> Option<(&str, &str)> {
+        self.modules
+            .get_key_value(name)
+            .map(|(name, source)| (name.as_str(), source.as_str()))
+    }
+}
+
+/// Adds or replaces a module, shaders set after that can include it.
+pub fn register_module(name: &str, source: &str) -> Result<(), String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_graphic() && c != '"') {
+        return Err(format!(
+            "Invalid module name {name:?}, it must be non-empty and have no spaces or quotes"
+        ));
+    }
+    LIBRARY.with_borrow_mut(|library| library.insert(name, source));
+    Ok(())
+}
+
+pub fn with_library<R>(f: impl FnOnce(&ShaderLibrary) -> R) -> R {
+    LIBRARY.with_borrow(f)
+}
+
+/// Name of the module, if `line` is `#include "name"`, trailing comment is allowed.
+fn include_directive(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start();
+    let rest = rest.strip_prefix("include")?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+    let (name, rest) = rest.split_once('"')?;
+    let rest = rest.trim();
+    (rest.is_empty() || rest.starts_with("//")).then_some(name)
+}
+
+/// Lines of code, which came from a single file.
+pub struct Chunk<'a> {
+    /// Included module, `None` for code passed to `Includes::expand()`
+    pub module: Option<&'a str>,
+    /// 1-based line of the file, where the chunk starts
+    pub first_line: u32,
+    pub text: String,
+    line_count: u32,
+}
+
+impl<'a> Chunk<'a> {
+    fn new(module: Option<&'a str>, first_line: u32) -> Self {
+        Self {
+            module,
+            first_line,
+            text: String::new(),
+            line_count: 0,
+        }
+    }
+
+    fn push_line(&mut self, line: &str) {
+        if self.line_count > 0 {
+            self.text.push('\n');
+        }
+        self.text.push_str(line);
+        self.line_count += 1;
+    }
+}
+
+/// Expands includes of all code of a shader.
+///
+/// Every module is included once per shader, as if it had include guards. Unknown modules and
+/// include cycles are replaced with `#error`, so they are reported by the compiler at the line
+/// of the directive.
+pub struct Includes<'a> {
+    library: &'a ShaderLibrary,
+    included: BTreeSet<&'a str>,
+    /// Modules being expanded, the last one is the innermost
+    stack: Vec<&'a str>,
+}
+
+impl<'a> Includes<'a> {
+    pub fn new(library: &'a ShaderLibrary) -> Self {
+        Self {
+            library,
+            included: BTreeSet::new(),
+            stack: Vec::new(),
+        }
+    }
+
+    /// Splits `code` into chunks, with chunks of included modules in place of directives.
+    pub fn expand(&mut self, code: &'a str) -> Vec<Chunk<'a>> {
+        let mut chunks = Vec::new();
+        self.expand_into(code, None, &mut chunks);
+        chunks
+    }
+
+    fn expand_into(&mut self, code: &'a str, module: Option<&'a str>, chunks: &mut Vec<Chunk<'a>>) {
+        let mut chunk = Chunk::new(module, 1);
+        for (index, line) in code.split('\n').enumerate() {
+            let Some(name) = include_directive(line) else {
+                chunk.push_line(line);
+                continue;
+            };
+            if let Some(start) = self.stack.iter().position(|&module| module == name) {
+                let cycle = self.stack[start..].join(" -> ");
+                chunk.push_line(&format!("#error include cycle {cycle} -> {name}"));
+                continue;
+            }
+            let Some((name, source)) = self.library.get(name) else {
+                chunk.push_line(&format!("#error unknown module {name}"));
+                continue;
+            };
+            if !self.included.insert(name) {
+                // Keeps numbering of lines after the directive
+                chunk.push_line("");
+                continue;
+            }
+
+            if chunk.line_count > 0 {
+                chunks.push(chunk);
+            }
+            self.stack.push(name);
+            self.expand_into(source, Some(name), chunks);
+            self.stack.pop();
+            chunk = Chunk::new(module, index as u32 + 2);
+        }
+        if chunk.line_count > 0 {
+            chunks.push(chunk);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library(modules: &[(&str, &str)]) -> ShaderLibrary {
+        let mut library = ShaderLibrary::default();
+        for (name, source) in modules {
+            library.insert(name, source);
+        }
+        library
+    }
+
+    /// Module, first line and text of each chunk.
+    fn expand<'a>(
+        includes: &mut Includes<'a>,
+        code: &'a str,
+    ) -> Vec<(Option<&'a str>, u32, String)> {
+        includes
+            .expand(code)
+            .into_iter()
+            .map(|chunk| (chunk.module, chunk.first_line, chunk.text))
+            .collect()
+    }
+
+    #[test]
+    fn module_is_placed_instead_of_directive() {
+        let library = library(&[("noise", "float a;\nfloat b;")]);
+        let mut includes = Includes::new(&library);
+        let chunks = expand(
+            &mut includes,
+            "// head\n  #  include \"noise\" // noise\nvoid f();",
+        );
+        assert_eq!(
+            chunks,
+            [
+                (None, 1, "// head".to_owned()),
+                (Some("noise"), 1, "float a;\nfloat b;".to_owned()),
+                (None, 3, "void f();".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn directive_with_trailing_code_is_kept() {
+        let library = library(&[("noise", "float a;")]);
+        let mut includes = Includes::new(&library);
+        let chunks = expand(&mut includes, "#include \"noise\" float b;");
+        assert_eq!(
+            chunks,
+            [(None, 1, "#include \"noise\" float b;".to_owned())]
+        );
+    }
+
+    #[test]
+    fn module_is_included_once() {
+        let library = library(&[
+            ("base", "float base;"),
+            ("noise", "#include \"base\"\nfloat noise;"),
+        ]);
+        let mut includes = Includes::new(&library);
+        let common = expand(
+            &mut includes,
+            "#include \"noise\"\n#include \"base\"\nfloat c;",
+        );
+        assert_eq!(
+            common,
+            [
+                (Some("base"), 1, "float base;".to_owned()),
+                (Some("noise"), 2, "float noise;".to_owned()),
+                // Repeated directive becomes an empty line, so numbering is kept
+                (None, 2, "\nfloat c;".to_owned()),
+            ]
+        );
+        // Code of the pass shares includes with common code
+        let user = expand(&mut includes, "#include \"noise\"\nfloat u;");
+        assert_eq!(user, [(None, 1, "\nfloat u;".to_owned())]);
+    }
+
+    #[test]
+    fn include_cycle_is_an_error() {
+        let library = library(&[
+            ("a", "#include \"b\"\nfloat a;"),
+            ("b", "#include \"a\"\nfloat b;"),
+            ("self", "#include \"self\""),
+        ]);
+        let mut includes = Includes::new(&library);
+        let chunks = expand(&mut includes, "#include \"a\"\n#include \"self\"");
+        assert_eq!(
+            chunks,
+            [
+                (
+                    Some("b"),
+                    1,
+                    "#error include cycle a -> b -> a\nfloat b;".to_owned()
+                ),
+                (Some("a"), 2, "float a;".to_owned()),
+                (
+                    Some("self"),
+                    1,
+                    "#error include cycle self -> self".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_module_is_an_error() {
+        let library = library(&[]);
+        let mut includes = Includes::new(&library);
+        let chunks = expand(&mut includes, "float a;\n#include \"missing\"\nfloat b;");
+        assert_eq!(
+            chunks,
+            [(
+                None,
+                1,
+                "float a;\n#error unknown module missing\nfloat b;".to_owned()
+            )]
+        );
+    }
+}
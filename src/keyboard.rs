@@ -0,0 +1,133 @@
+//! Keyboard state for `iChannelN`, laid out as on Shadertoy.
+//!
+//! Texture is 256x3 with a single 8-bit channel, column is `keyCode` of the key:
+//! row 0 is 1.0 while key is held, row 1 is 1.0 on the frame key was pressed,
+//! row 2 flips every time key is pressed.
+
+use minwebgl as gl;
+use web_sys::{WebGl2RenderingContext as GL, WebGlTexture};
+
+const KEY_COUNT: usize = 256;
+const DOWN_ROW: usize = 0;
+const PRESSED_ROW: usize = 1;
+const TOGGLE_ROW: usize = 2;
+const ROW_COUNT: usize = 3;
+
+pub struct Keyboard {
+    state: [u8; KEY_COUNT * ROW_COUNT],
+    texture: Option<WebGlTexture>,
+    /// State changed since the last upload
+    dirty: bool,
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self {
+            state: [0; KEY_COUNT * ROW_COUNT],
+            texture: None,
+            dirty: true,
+        }
+    }
+}
+
+impl Keyboard {
+    fn cell(&mut self, row: usize, key: usize) -> &mut u8 {
+        &mut self.state[row * KEY_COUNT + key]
+    }
+
+    pub fn key_down(&mut self, key_code: u32) {
+        let key = key_code as usize;
+        // Auto-repeat sends keydown without keyup
+        if key >= KEY_COUNT || *self.cell(DOWN_ROW, key) != 0 {
+            return;
+        }
+        *self.cell(DOWN_ROW, key) = u8::MAX;
+        *self.cell(PRESSED_ROW, key) = u8::MAX;
+        *self.cell(TOGGLE_ROW, key) ^= u8::MAX;
+        self.dirty = true;
+    }
+
+    pub fn key_up(&mut self, key_code: u32) {
+        let key = key_code as usize;
+        if key < KEY_COUNT {
+            *self.cell(DOWN_ROW, key) = 0;
+            self.dirty = true;
+        }
+    }
+
+    /// Releases all keys, used when page loses focus and keyup events are not delivered.
+    pub fn release_all(&mut self) {
+        self.state[..KEY_COUNT].fill(0);
+        self.dirty = true;
+    }
+
+    /// Clears keys pressed on the frame that was just rendered, returns `true` if state changed.
+    pub fn end_frame(&mut self) -> bool {
+        let pressed = &mut self.state[PRESSED_ROW * KEY_COUNT..(PRESSED_ROW + 1) * KEY_COUNT];
+        let changed = pressed.iter().any(|&value| value != 0);
+        if changed {
+            pressed.fill(0);
+            self.dirty = true;
+        }
+        changed
+    }
+
+    /// Creates texture and uploads changed state.
+    pub fn prepare(&mut self, gl: &GL) {
+        if self.texture.is_none() {
+            let Some(texture) = gl.create_texture() else {
+                return;
+            };
+            gl.bind_texture(GL::TEXTURE_2D, Some(&texture));
+            gl.tex_storage_2d(
+                GL::TEXTURE_2D,
+                1,
+                GL::R8,
+                KEY_COUNT as i32,
+                ROW_COUNT as i32,
+            );
+            gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::NEAREST as i32);
+            gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::NEAREST as i32);
+            gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+            gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+            self.texture = Some(texture);
+            self.dirty = true;
+        }
+        if !self.dirty {
+            return;
+        }
+
+        gl.bind_texture(GL::TEXTURE_2D, self.texture.as_ref());
+        // Rows are 256 bytes, so default alignment of 4 fits
+        if let Err(error) = gl.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_u8_array(
+            GL::TEXTURE_2D,
+            0,
+            0,
+            0,
+            KEY_COUNT as i32,
+            ROW_COUNT as i32,
+            GL::RED,
+            GL::UNSIGNED_BYTE,
+            Some(&self.state),
+        ) {
+            gl::error!("Failed to upload keyboard texture: {error:?}");
+        }
+        gl.bind_texture(GL::TEXTURE_2D, None);
+        self.dirty = false;
+    }
+
+    /// Forgets texture of the lost context, it is created again by `prepare()`.
+    pub fn invalidate(&mut self) {
+        self.texture = None;
+    }
+
+    /// Uploaded texture and its `iChannelResolution`.
+    pub fn get(&self) -> Option<(&WebGlTexture, [f32; 3])> {
+        let texture = self.texture.as_ref()?;
+        Some((texture, [KEY_COUNT as f32, ROW_COUNT as f32, 1.0]))
+    }
+
+    pub fn delete(&mut self, gl: &GL) {
+        gl.delete_texture(self.texture.take().as_ref());
+    }
+}
@@ -1,579 +1,265 @@
-use core::sync::atomic::AtomicBool;
-use js_sys::Date;
+mod adaptive;
+mod capture;
+mod error;
+mod events;
+mod include;
+mod keyboard;
+mod panel;
+mod params;
+mod pipeline;
+mod player;
+mod pointer;
+mod program;
+mod project;
+mod shader;
+mod state;
+mod stats;
+mod texture;
+
+use error::{ErrorDetail, PlayerError};
 use minwebgl as gl;
-use serde::Deserialize;
-use std::sync::{atomic::Ordering, Mutex, OnceLock};
-use wasm_bindgen::{
-    closure::{Closure, IntoWasmClosure},
-    convert::FromWasmAbi,
-    prelude::wasm_bindgen,
-    JsCast, JsValue,
-};
-use web_sys::{window, CustomEvent, Element, EventTarget, WebGl2RenderingContext as GL};
-
-#[derive(Clone, Copy, Deserialize, Debug)]
-struct ResolutionUniform {
-    width: f32,
-    height: f32,
-    pixel_aspect_ratio: f32,
+use player::ShaderPlayer;
+use serde::Serialize;
+use std::cell::RefCell;
+use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsValue};
+use web_sys::{window, CustomEvent, EventTarget, HtmlCanvasElement, UrlSearchParams};
+
+/// Player on the page canvas, driven by the exported free functions.
+///
+/// It is created by the first call, so pages which only use `ShaderPlayer` don't get an extra canvas.
+enum DefaultPlayer {
+    NotCreated,
+    Created(ShaderPlayer),
+    /// Creation failed and was reported, it is not retried
+    Failed,
 }
 
-#[derive(Clone, Copy, Deserialize, Debug, Default)]
-struct MouseUniform {
-    x: f32,
-    y: f32,
-    down_x: f32,
-    down_y: f32,
+thread_local! {
+    static DEFAULT_PLAYER: RefCell<DefaultPlayer> = const { RefCell::new(DefaultPlayer::NotCreated) };
 }
 
-#[derive(Clone, Copy, Deserialize, Debug)]
-struct DateUniform {
-    year: f32,
-    month: f32,
-    day: f32,
-    time: f32,
+/// Creates the default player, if it isn't created yet. Free functions call it themselves.
+///
+/// Canvas with id or class `canvas` is used, otherwise a canvas filling the page is added.
+/// Throws `context` error if the player can't be created, and `not_initialized` on later calls.
+#[wasm_bindgen]
+pub fn init_default_player() -> Result<(), JsValue> {
+    let created = DEFAULT_PLAYER.with_borrow(|player| match player {
+        DefaultPlayer::NotCreated => None,
+        DefaultPlayer::Created(_) => Some(true),
+        DefaultPlayer::Failed => Some(false),
+    });
+    match created {
+        Some(true) => return Ok(()),
+        Some(false) => return Err(reject(PlayerError::NotInitialized)),
+        None => {}
+    }
+    // Borrow is released, so a failed creation can report its error
+    let (player, result) = match create_default_player() {
+        Ok(player) => (DefaultPlayer::Created(player), Ok(())),
+        Err(error) => (DefaultPlayer::Failed, Err(reject(error))),
+    };
+    DEFAULT_PLAYER.with_borrow_mut(|default_player| *default_player = player);
+    result
 }
 
-#[derive(Clone, Copy, Deserialize, Debug, Default)]
-struct Uniforms {
-    resolution: Option<ResolutionUniform>,
-    time: Option<f32>,
-    time_delta: Option<f32>,
-    frame: Option<f32>,
-    frame_rate: Option<f32>,
-    mouse: Option<MouseUniform>,
-    date: Option<DateUniform>,
+/// Calls `f` with the default player, which is created on the first call.
+fn with_default_player<R>(f: impl FnOnce(&ShaderPlayer) -> R) -> Result<R, JsValue> {
+    init_default_player()?;
+    DEFAULT_PLAYER.with_borrow(|player| match player {
+        DefaultPlayer::Created(player) => Ok(f(player)),
+        _ => Err(reject(PlayerError::NotInitialized)),
+    })
 }
 
-#[derive(Clone, Copy, Deserialize, Debug, Default)]
-struct Playback {
-    paused: Option<bool>,
-    speed: Option<f32>,
+#[wasm_bindgen]
+pub async fn set_fragment_shader(new_shader_code: String) -> Result<(), JsValue> {
+    let promise = with_default_player(|player| player.set_fragment_shader(&new_shader_code))?;
+    wasm_bindgen_futures::JsFuture::from(promise).await?;
+    Ok(())
 }
 
-#[derive(Clone, Copy, Deserialize, Debug, Default)]
-struct PlayerState {
-    playback: Option<Playback>,
-    uniforms: Option<Uniforms>,
+#[wasm_bindgen]
+pub async fn set_project(project: JsValue) -> Result<(), JsValue> {
+    let promise = with_default_player(|player| player.set_project(project))?;
+    wasm_bindgen_futures::JsFuture::from(promise).await?;
+    Ok(())
 }
 
-static PLAYER_STATE_STORAGE: OnceLock<Mutex<PlayerState>> = OnceLock::new();
-static FRAGMENT_SHADER_STORAGE: OnceLock<Mutex<String>> = OnceLock::new();
-static RELOAD_FRAGMENT_SHADER: AtomicBool = AtomicBool::new(false);
-static LOST_WEBGL2_CONTEXT: AtomicBool = AtomicBool::new(false);
-static MOUSE_DOWN: AtomicBool = AtomicBool::new(false);
-
 #[wasm_bindgen]
-pub fn set_fragment_shader(new_shader_code: &str) {
-    if let Some(mutex) = FRAGMENT_SHADER_STORAGE.get() {
-        if let Ok(mut shader) = mutex.lock() {
-            *shader = prepare_shader(new_shader_code);
-        } else {
-            report_error("Failed to lock mutex: don't change shader in separate threads");
-            return;
-        }
-    } else if FRAGMENT_SHADER_STORAGE
-        .set(Mutex::new(prepare_shader(new_shader_code)))
-        .is_err()
-    {
-        report_error("Failed to init mutex: don't change shader in separate threads");
-        return;
-    }
-
-    RELOAD_FRAGMENT_SHADER.store(true, Ordering::Relaxed);
+pub async fn set_channel_texture(
+    channel: usize,
+    source: JsValue,
+    options: JsValue,
+) -> Result<(), JsValue> {
+    let promise =
+        with_default_player(|player| player.set_channel_texture(channel, source, options))?;
+    wasm_bindgen_futures::JsFuture::from(promise).await?;
+    Ok(())
 }
 
 #[wasm_bindgen]
-pub fn update_player_state(state: JsValue) {
-    match serde_wasm_bindgen::from_value::<PlayerState>(state) {
-        Ok(state) => {
-            if let Some(mutex) = PLAYER_STATE_STORAGE.get() {
-                if let Ok(mut player_state) = mutex.lock() {
-                    if let Some(uniforms) = &mut player_state.uniforms {
-                        if let Some(new_uniforms) = state.uniforms {
-                            uniforms.resolution = new_uniforms.resolution.or(uniforms.resolution);
-                            uniforms.time = new_uniforms.time.or(uniforms.time);
-                            uniforms.time_delta = new_uniforms.time_delta.or(uniforms.time_delta);
-                            uniforms.frame = new_uniforms.frame.or(uniforms.frame);
-                            uniforms.frame_rate = new_uniforms.frame_rate.or(uniforms.frame_rate);
-                            uniforms.mouse = new_uniforms.mouse.or(uniforms.mouse);
-                            uniforms.date = new_uniforms.date.or(uniforms.date);
-                        }
-                    } else {
-                        player_state.uniforms = state.uniforms;
-                    }
-
-                    if let Some(playback) = &mut player_state.playback {
-                        if let Some(new_playback) = state.playback {
-                            playback.paused = new_playback.paused.or(playback.paused);
-                            playback.speed = new_playback.speed.or(playback.speed);
-                        }
-                    } else {
-                        player_state.playback = state.playback;
-                    }
-                } else {
-                    gl::error!("Failed to lock player state mutex");
-                }
-            } else if PLAYER_STATE_STORAGE.set(Mutex::new(state)).is_err() {
-                report_error("Failed to init mutex: don't change player state in separate threads");
-            }
-        }
-        Err(error) => report_error(&format!("Unkown player state format: {error:?}")),
-    }
+pub fn update_player_state(state: JsValue) -> Result<(), JsValue> {
+    with_default_player(|player| player.update_player_state(state))?
 }
 
 #[wasm_bindgen]
-pub fn play() {
-    set_paused(false);
+pub fn get_player_state() -> Result<JsValue, JsValue> {
+    with_default_player(ShaderPlayer::get_player_state)?
 }
 
 #[wasm_bindgen]
-pub fn stop() {
-    set_paused(true);
+pub fn get_shader_params() -> Result<JsValue, JsValue> {
+    with_default_player(ShaderPlayer::get_shader_params)?
 }
 
-fn set_paused(value: bool) {
-    if let Some(mutex) = PLAYER_STATE_STORAGE.get() {
-        if let Ok(mut player_state) = mutex.lock() {
-            if let Some(playback) = &mut player_state.playback {
-                playback.paused = Some(value);
-            } else {
-                player_state.playback = Some(Playback {
-                    paused: Some(value),
-                    ..Default::default()
-                });
-            }
-        } else {
-            gl::error!("Failed to lock player state mutex");
-        }
-    } else if PLAYER_STATE_STORAGE
-        .set(Mutex::new(PlayerState {
-            playback: Some(Playback {
-                paused: Some(value),
-                ..Default::default()
-            }),
-            ..Default::default()
-        }))
-        .is_err()
-    {
-        report_error("Failed to init mutex: don't change player state in separate threads");
-    }
+#[wasm_bindgen]
+pub fn screenshot(options: JsValue) -> Result<Vec<u8>, JsValue> {
+    with_default_player(|player| player.screenshot(options))?
 }
 
-pub fn report_error(message: &str) {
-    gl::error!("{}", message);
-    let event_init = web_sys::CustomEventInit::new();
-    event_init.set_detail(&JsValue::from_str(message));
-    let event = match CustomEvent::new_with_event_init_dict("WasmErrorEvent", &event_init) {
-        Ok(event) => event,
-        Err(error) => {
-            gl::error!("Failed to create custom event: {:?}", error);
-            return;
-        }
-    };
-
-    let target: EventTarget = if let Some(window) = window() {
-        window.into()
-    } else {
-        gl::error!("Failed to get window for event dispatch");
-        return;
-    };
-
-    if let Err(error) = target.dispatch_event(&event) {
-        gl::error!("Failed to dispatch event {error:?}");
-    }
+#[wasm_bindgen]
+pub async fn render_frames(
+    options: JsValue,
+    callback: js_sys::Function,
+) -> Result<JsValue, JsValue> {
+    let promise = with_default_player(|player| player.render_frames(options, callback))?;
+    wasm_bindgen_futures::JsFuture::from(promise).await
 }
 
-fn prepare_shader(shadertoy_code: &str) -> String {
-    format!("#version 300 es 
-precision mediump float;
-
-uniform vec3 u_resolution; // image/buffer	The viewport resolution (z is pixel aspect ratio, usually 1.0)
-uniform float	u_time; // image/sound/buffer	Current time in seconds
-uniform float	u_time_delta; // image/buffer	Time it takes to render a frame, in seconds
-uniform int	u_frame; // image/buffer	Current frame
-uniform float	u_frame_rate; // image/buffer	Number of frames rendered per second
-uniform vec4	u_mouse; // image/buffer	xy = current pixel coords (if LMB is down). zw = click pixel
-uniform vec4	u_date; // image/buffer/sound	Year, month, day, time in seconds in .xyzw
-{shadertoy_code}
-in vec2 vUv;
-out vec4 frag_color;
-
-void main() {{
-    render_image(frag_color, vUv * u_resolution.xy);
-}}")
+/// Shows or hides controls of playback and shader parameters next to the canvas.
+#[wasm_bindgen]
+pub fn show_controls(visible: bool) -> Result<(), JsValue> {
+    with_default_player(|player| player.show_controls(visible))?
 }
 
-fn get_shader() -> Option<String> {
-    Some(FRAGMENT_SHADER_STORAGE.get()?.lock().ok()?.to_owned())
+#[wasm_bindgen]
+pub fn play() -> Result<(), JsValue> {
+    with_default_player(ShaderPlayer::play)
 }
 
-fn add_event_listener<F: IntoWasmClosure<dyn FnMut(E)> + 'static, E: FromWasmAbi + 'static>(
-    event_target: &EventTarget,
-    event_type: &str,
-    f: F,
-) {
-    let closure: Closure<dyn FnMut(E)> = Closure::new(f);
-    let callback = closure.as_ref().unchecked_ref::<js_sys::Function>();
-    if let Err(error) = event_target.add_event_listener_with_callback(event_type, callback) {
-        gl::error!("Can not subscribe to canvas events{:?}", error);
-    }
-    closure.forget();
+#[wasm_bindgen]
+pub fn stop() -> Result<(), JsValue> {
+    with_default_player(ShaderPlayer::stop)
 }
 
-fn update_mouse_uniform(update: &dyn Fn(Option<MouseUniform>) -> Option<MouseUniform>) {
-    if let Some(mutex) = PLAYER_STATE_STORAGE.get() {
-        if let Ok(mut player_state) = mutex.lock() {
-            if let Some(uniforms) = &mut player_state.uniforms {
-                uniforms.mouse = update(uniforms.mouse);
-            } else {
-                player_state.uniforms = Some(Uniforms {
-                    mouse: update(None),
-                    ..Default::default()
-                });
-            }
-        } else {
-            gl::error!("Failed to lock player state mutex");
-        }
-    } else if PLAYER_STATE_STORAGE
-        .set(Mutex::new(PlayerState {
-            uniforms: Some(Uniforms {
-                mouse: update(None),
-                ..Default::default()
-            }),
-            ..Default::default()
-        }))
-        .is_err()
-    {
-        report_error("Failed to init mutex: don't change player state in separate threads");
-    }
+#[wasm_bindgen]
+pub fn seek(seconds: f64) -> Result<(), JsValue> {
+    with_default_player(|player| player.seek(seconds))
 }
 
-fn run() -> Result<(), gl::WebglError> {
-    gl::browser::setup(minwebgl::browser::Config::default());
-    let canvas = gl::canvas::retrieve_or_make()?;
-    let gl = gl::context::from_canvas(&canvas)?;
-
-    add_event_listener(
-        &canvas.clone().into(),
-        "webglcontextlost",
-        move |event: web_sys::Event| {
-            gl::error!("Canvas lost WebGL2 context");
-            event.prevent_default();
-            LOST_WEBGL2_CONTEXT.store(true, Ordering::Relaxed);
-        },
-    );
-
-    add_event_listener(
-        &canvas.clone().into(),
-        "webglcontextrestored",
-        move |_: web_sys::Event| {
-            gl::info!("Canvas restored WebGL2 context");
-            LOST_WEBGL2_CONTEXT.store(false, Ordering::Relaxed);
-        },
-    );
-
-    let canvas_clone = canvas.clone();
-    add_event_listener(
-        &canvas.clone().into(),
-        "mousedown",
-        move |mouse_event: web_sys::MouseEvent| {
-            let rect = canvas_clone
-                .unchecked_ref::<Element>()
-                .get_bounding_client_rect();
-            let x = mouse_event.client_x() as f32 - rect.left() as f32;
-            let y = mouse_event.client_y() as f32 - rect.top() as f32;
-            update_mouse_uniform(&|_| {
-                Some(MouseUniform {
-                    x,
-                    y,
-                    down_x: x,
-                    down_y: y,
-                })
-            });
-            MOUSE_DOWN.store(true, Ordering::Relaxed);
-        },
-    );
-
-    add_event_listener(
-        &canvas.clone().into(),
-        "mouseup",
-        move |_: web_sys::MouseEvent| {
-            MOUSE_DOWN.store(false, Ordering::Relaxed);
-        },
-    );
-
-    let canvas_clone = canvas.clone();
-    add_event_listener(
-        &canvas.clone().into(),
-        "mousemove",
-        move |mouse_event: web_sys::MouseEvent| {
-            if MOUSE_DOWN.load(Ordering::Relaxed) {
-                let rect = canvas_clone
-                    .unchecked_ref::<Element>()
-                    .get_bounding_client_rect();
-                let x = mouse_event.client_x() as f32 - rect.left() as f32;
-                let y = mouse_event.client_y() as f32 - rect.top() as f32;
-                update_mouse_uniform(&|old_uniform| {
-                    Some(if let Some(old_uniform) = old_uniform {
-                        MouseUniform {
-                            x,
-                            y,
-                            ..old_uniform
-                        }
-                    } else {
-                        MouseUniform {
-                            x,
-                            y,
-                            down_x: x,
-                            down_y: y,
-                        }
-                    })
-                });
-            }
-        },
-    );
+#[wasm_bindgen]
+pub fn step_frames(n: u32) -> Result<(), JsValue> {
+    with_default_player(|player| player.step_frames(n))
+}
 
-    // Vertex and fragment shader source code
-    let vertex_shader_src = include_str!("../shaders/shader.vert");
-    let default_frag_shader_src = include_str!("../shaders/shader.frag");
-    let frag_shader = get_shader().unwrap_or(prepare_shader(default_frag_shader_src));
-    let mut program =
-        gl::ProgramFromSources::new(vertex_shader_src, &frag_shader).compile_and_link(&gl)?;
-    gl.use_program(Some(&program));
-    RELOAD_FRAGMENT_SHADER.store(false, Ordering::Relaxed);
+#[wasm_bindgen]
+pub fn restart() -> Result<(), JsValue> {
+    with_default_player(ShaderPlayer::restart)
+}
 
-    let mut last_real_time = 0f64;
-    let mut last_playback_time = 0f64;
-    let mut frame = 0f32;
-    let mut reload_webgl2_context = false;
-    let mut player_state = PlayerState::default();
+/// Makes GLSL module available to `#include "name"` in shaders of all players.
+///
+/// Shaders which are already compiled are not affected, set them again to use the new module.
+#[wasm_bindgen]
+pub fn register_shader_module(name: &str, source: &str) -> Result<(), JsValue> {
+    include::register_module(name, source)
+        .map_err(|message| reject(PlayerError::InvalidArgument(message)))
+}
 
-    let mut resolution_loc = gl.get_uniform_location(&program, "u_resolution");
-    let mut time_loc = gl.get_uniform_location(&program, "u_time");
-    let mut time_delta_loc = gl.get_uniform_location(&program, "u_time_delta");
-    let mut frame_loc = gl.get_uniform_location(&program, "u_frame");
-    let mut frame_rate_loc = gl.get_uniform_location(&program, "u_frame_rate");
-    let mut mouse_loc = gl.get_uniform_location(&program, "u_mouse");
-    let mut date_loc = gl.get_uniform_location(&program, "u_date");
+/// Reports `error` with `WasmErrorEvent`, one event per detail.
+pub fn report_error(error: &PlayerError) {
+    error.details().iter().for_each(report_error_detail);
+}
 
-    // Define the update and draw logic
-    let update_and_draw = move |mut t: f64| {
-        t /= 1000f64;
-        let mut force_reload_shader = false;
-        match (
-            LOST_WEBGL2_CONTEXT.load(Ordering::Relaxed),
-            reload_webgl2_context,
-        ) {
-            (true, false) => {
-                // Free resources
-                gl.delete_program(Some(&program));
-                reload_webgl2_context = true;
-                return true;
-            }
-            (true, true) => {
-                return true;
-            }
-            (false, true) => {
-                gl::info!("forsing shader reload");
-                force_reload_shader = true;
-                reload_webgl2_context = false;
-            }
-            _ => {}
-        }
+/// Reports `error` and converts it to a value thrown to JS.
+pub fn reject(error: PlayerError) -> JsValue {
+    report_error(&error);
+    error.into()
+}
 
-        if force_reload_shader || RELOAD_FRAGMENT_SHADER.load(Ordering::Relaxed) {
-            let fragment_shader = get_shader().unwrap_or(prepare_shader(default_frag_shader_src));
-            let new_program = gl::ProgramFromSources::new(vertex_shader_src, &fragment_shader)
-                .compile_and_link(&gl);
-            match new_program {
-                Ok(new_program) => {
-                    program = new_program;
-                    gl.use_program(Some(&program));
-                    resolution_loc = gl.get_uniform_location(&program, "u_resolution");
-                    time_loc = gl.get_uniform_location(&program, "u_time");
-                    time_delta_loc = gl.get_uniform_location(&program, "u_time_delta");
-                    frame_loc = gl.get_uniform_location(&program, "u_frame");
-                    frame_rate_loc = gl.get_uniform_location(&program, "u_frame_rate");
-                    mouse_loc = gl.get_uniform_location(&program, "u_mouse");
-                    date_loc = gl.get_uniform_location(&program, "u_date");
-                    gl::info!("shader reloaded");
-                }
-                Err(error) => {
-                    report_error(&format!("Shader compilation error: {error}"));
-                }
-            }
-            RELOAD_FRAGMENT_SHADER.store(false, Ordering::Relaxed);
-        }
+pub fn report_error_detail(detail: &ErrorDetail) {
+    gl::error!("{}", detail);
+    let Some(window) = window() else {
+        gl::error!("Failed to get window for event dispatch");
+        return;
+    };
+    dispatch_event(&window, "WasmErrorEvent", detail);
+}
 
-        // Disable render if paused
-        player_state = if let Some(player_state_mutex) = PLAYER_STATE_STORAGE.get() {
-            player_state_mutex.try_lock().as_deref().cloned().ok()
-        } else {
-            None
+/// Dispatches `CustomEvent` with `detail` converted to a plain JS object.
+pub fn dispatch_event(target: &EventTarget, event_type: &str, detail: &impl Serialize) {
+    let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+    let detail = match detail.serialize(&serializer) {
+        Ok(detail) => detail,
+        Err(error) => {
+            gl::error!("Failed to serialize {event_type} detail: {:?}", error);
+            return;
         }
-        .unwrap_or(player_state);
-        if let Some(Playback {
-            paused: Some(true), ..
-        }) = player_state.playback
-        {
-            // Do nothing, except update last_real_time to prevent accumulation of time_delta
-            last_real_time = t;
-            return true;
+    };
+    let event_init = web_sys::CustomEventInit::new();
+    event_init.set_detail(&detail);
+    let event = match CustomEvent::new_with_event_init_dict(event_type, &event_init) {
+        Ok(event) => event,
+        Err(error) => {
+            gl::error!("Failed to create custom event: {:?}", error);
+            return;
         }
+    };
 
-        // u_resolution
-        if let Some(Uniforms {
-            resolution: Some(resolution),
-            ..
-        }) = player_state.uniforms
-        {
-            gl.uniform3f(
-                resolution_loc.as_ref(),
-                resolution.width,
-                resolution.height,
-                resolution.pixel_aspect_ratio,
-            );
-        } else {
-            gl.uniform3f(
-                resolution_loc.as_ref(),
-                gl.drawing_buffer_width() as f32,
-                gl.drawing_buffer_height() as f32,
-                if let Some(window) = web_sys::window() {
-                    window.device_pixel_ratio() as f32
-                } else {
-                    1.0
-                },
-            );
-        };
-
-        // This code is designed to seamlessly continue playback after `Resume`
-        let (time, time_delta) = if last_real_time == 0.0 {
-            // First frame, just init
-            last_playback_time = t;
-            (last_playback_time, 0.0)
-        } else {
-            let real_time_delta = t - last_real_time;
-            let playback_time_delta = real_time_delta
-                * f64::from(
-                    if let Some(Playback {
-                        speed: Some(speed), ..
-                    }) = player_state.playback
-                    {
-                        speed
-                    } else {
-                        1.0
-                    },
-                );
-            last_playback_time += playback_time_delta;
-            (last_playback_time, playback_time_delta)
-        };
-
-        // u_time
-        gl.uniform1f(
-            time_loc.as_ref(),
-            if let Some(Uniforms {
-                time: Some(fixed_time),
-                ..
-            }) = player_state.uniforms
-            {
-                fixed_time
-            } else {
-                time as f32
-            },
-        );
-
-        // u_time_delta
-        let time_delta = if let Some(Uniforms {
-            time_delta: Some(fixed_time_delta),
-            ..
-        }) = player_state.uniforms
-        {
-            fixed_time_delta
-        } else {
-            time_delta as f32
-        };
-        gl.uniform1f(time_delta_loc.as_ref(), time_delta);
-        last_real_time = t;
-
-        // u_frame
-        gl.uniform1f(
-            frame_loc.as_ref(),
-            if let Some(Uniforms {
-                frame: Some(fixed_frame),
-                ..
-            }) = player_state.uniforms
-            {
-                fixed_frame
-            } else {
-                frame
-            },
-        );
-        frame += 1f32;
-
-        // u_frame_rate
-        gl.uniform1f(
-            frame_rate_loc.as_ref(),
-            if let Some(Uniforms {
-                frame_rate: Some(fixed_frame_rate),
-                ..
-            }) = player_state.uniforms
-            {
-                fixed_frame_rate
-            } else {
-                1f32 / time_delta
-            },
-        );
+    if let Err(error) = target.dispatch_event(&event) {
+        gl::error!("Failed to dispatch event {error:?}");
+    }
+}
 
-        // u_mouse
-        if let Some(Uniforms {
-            mouse:
-                Some(MouseUniform {
-                    x,
-                    y,
-                    down_x,
-                    down_y,
-                }),
-            ..
-        }) = player_state.uniforms
-        {
-            gl.uniform4f(mouse_loc.as_ref(), x, y, down_x, down_y);
-        }
+/// Page address has `controls` query parameter, like `index.html?controls`.
+fn page_requests_controls() -> bool {
+    window()
+        .and_then(|window| window.location().search().ok())
+        .and_then(|search| UrlSearchParams::new_with_str(&search).ok())
+        .is_some_and(|params| params.has("controls"))
+}
 
-        // u_date
-        if let Some(Uniforms {
-            date: Some(replaced_date),
-            ..
-        }) = player_state.uniforms
-        {
-            gl.uniform4f(
-                date_loc.as_ref(),
-                replaced_date.year,
-                replaced_date.month,
-                replaced_date.day,
-                replaced_date.time,
-            );
-        } else {
-            let date = Date::new_0();
-            gl.uniform4f(
-                date_loc.as_ref(),
-                date.get_full_year() as f32,
-                date.get_month() as f32,
-                date.get_day() as f32,
-                (date.get_hours() * 3600 + date.get_minutes() * 60 + date.get_seconds()) as f32,
-            );
-        };
+/// Adds canvas filling the page, its drawing buffer follows CSS size, see `ShaderPlayer`.
+///
+/// Unlike `gl::canvas::make()`, no window resize handler is added, which would fight the player over the size.
+fn make_canvas() -> Result<HtmlCanvasElement, PlayerError> {
+    let failed =
+        |error: JsValue| PlayerError::Context(format!("Failed to create canvas: {error:?}"));
+    let document = window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| PlayerError::Context("Page has no document".to_owned()))?;
+    let canvas = document
+        .create_element("canvas")
+        .map_err(failed)?
+        .dyn_into::<HtmlCanvasElement>()
+        .map_err(|element| failed(element.into()))?;
+    let style = canvas.style();
+    style.set_property("width", "100%").map_err(failed)?;
+    style.set_property("height", "100%").map_err(failed)?;
+    style.set_property("display", "block").map_err(failed)?;
+    document
+        .body()
+        .ok_or_else(|| PlayerError::Context("Page has no body".to_owned()))?
+        .append_child(&canvas)
+        .map_err(failed)?;
+    Ok(canvas)
+}
 
-        // Draw points
-        gl.draw_arrays(GL::TRIANGLE_STRIP, 0, 4);
-        true
+fn create_default_player() -> Result<ShaderPlayer, PlayerError> {
+    let canvas = match gl::canvas::retrieve() {
+        Ok(canvas) => canvas,
+        Err(_) => make_canvas()?,
     };
-
-    // Run the render loop
-    gl::exec_loop::run(update_and_draw);
-    Ok(())
+    let player = ShaderPlayer::from_canvas(canvas)?;
+    if page_requests_controls() {
+        // Error is already reported, the player works without controls
+        let _ = player.show_controls(true);
+    }
+    Ok(player)
 }
 
 fn main() {
-    run().unwrap();
+    // Default player is created on demand, see `init_default_player()`
+    gl::browser::setup(minwebgl::browser::Config::default());
 }
@@ -0,0 +1,301 @@
+//! Optional controls overlay placed over the canvas: playback controls and parameters of the shader.
+
+use crate::params::{ParamControl, ParamType, ParamValue, ParamValues, ShaderParam};
+use minwebgl as gl;
+use std::rc::Rc;
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{Document, Element, HtmlCanvasElement, HtmlElement, HtmlInputElement};
+
+/// Class of the panel element, rows have classes `shader-controls-playback` and `shader-controls-param`
+const PANEL_CLASS: &str = "shader-controls";
+/// Default look, pages override it with `.shader-controls` rules marked `!important`
+const PANEL_STYLE: &str = "position: absolute; z-index: 1; display: flex; flex-direction: column; \
+    gap: 4px; padding: 8px; max-height: 90%; overflow-y: auto; background: rgba(0, 0, 0, 0.6); \
+    color: white; font: 12px sans-serif; border-bottom-right-radius: 4px;";
+/// Range and step of the speed slider, the API accepts any speed
+const SPEED_RANGE: [&str; 3] = ["-2", "2", "0.1"];
+
+/// Change made with one of controls.
+pub enum PanelAction {
+    TogglePause,
+    Speed(f32),
+    Param(String, ParamValue),
+}
+
+type Listener = Closure<dyn FnMut(web_sys::Event)>;
+
+fn create<T: JsCast>(document: &Document, tag: &str) -> Result<T, JsValue> {
+    document
+        .create_element(tag)?
+        .dyn_into::<T>()
+        .map_err(JsValue::from)
+}
+
+fn create_input(document: &Document, input_type: &str) -> Result<HtmlInputElement, JsValue> {
+    let input = create::<HtmlInputElement>(document, "input")?;
+    input.set_type(input_type);
+    Ok(input)
+}
+
+fn listen(
+    target: &Element,
+    event_type: &str,
+    handler: impl FnMut(web_sys::Event) + 'static,
+) -> Result<Listener, JsValue> {
+    let closure = Listener::new(handler);
+    target.add_event_listener_with_callback(event_type, closure.as_ref().unchecked_ref())?;
+    Ok(closure)
+}
+
+/// `#rrggbb` of the first three components, as `<input type="color">` takes it.
+fn color_hex(components: &[f32]) -> String {
+    let channel = |index: usize| {
+        let value = components.get(index).copied().unwrap_or(0.0);
+        (value.clamp(0.0, 1.0) * 255.0).round() as u8
+    };
+    format!("#{:02x}{:02x}{:02x}", channel(0), channel(1), channel(2))
+}
+
+/// Panel element with its controls, removed from the page on drop.
+pub struct ControlPanel {
+    document: Document,
+    root: HtmlElement,
+    pause: HtmlElement,
+    speed: HtmlInputElement,
+    speed_text: HtmlElement,
+    /// Container of parameter rows
+    param_rows: HtmlElement,
+    /// Parameters of the current shader
+    params: Vec<ShaderParam>,
+    on_action: Rc<dyn Fn(PanelAction)>,
+    /// Listeners of playback controls
+    listeners: Vec<Listener>,
+    /// Listeners of parameter rows, replaced together with rows
+    param_listeners: Vec<Listener>,
+}
+
+impl ControlPanel {
+    /// Inserts the panel after `canvas`, changes made with controls are passed to `on_action`.
+    pub fn new(
+        canvas: &HtmlCanvasElement,
+        on_action: impl Fn(PanelAction) + 'static,
+    ) -> Result<Self, JsValue> {
+        let document = canvas
+            .owner_document()
+            .ok_or_else(|| JsValue::from_str("Canvas has no document"))?;
+        let on_action: Rc<dyn Fn(PanelAction)> = Rc::new(on_action);
+
+        let root = create::<HtmlElement>(&document, "div")?;
+        root.set_class_name(PANEL_CLASS);
+        root.style().set_css_text(PANEL_STYLE);
+
+        let playback = create::<HtmlElement>(&document, "div")?;
+        playback.set_class_name("shader-controls-playback");
+        let pause = create::<HtmlElement>(&document, "button")?;
+        let speed_label = create::<HtmlElement>(&document, "label")?;
+        speed_label.set_inner_text(" Speed ");
+        let speed = create_input(&document, "range")?;
+        let [min, max, step] = SPEED_RANGE;
+        speed.set_min(min);
+        speed.set_max(max);
+        speed.set_step(step);
+        let speed_text = create::<HtmlElement>(&document, "output")?;
+        playback.append_with_node_1(&pause)?;
+        playback.append_with_node_1(&speed_label)?;
+        speed_label.append_with_node_1(&speed)?;
+        playback.append_with_node_1(&speed_text)?;
+
+        let param_rows = create::<HtmlElement>(&document, "div")?;
+        param_rows
+            .style()
+            .set_css_text("display: flex; flex-direction: column; gap: 4px;");
+        root.append_with_node_1(&playback)?;
+        root.append_with_node_1(&param_rows)?;
+
+        let listeners = vec![
+            {
+                let on_action = on_action.clone();
+                listen(&pause, "click", move |_| {
+                    on_action(PanelAction::TogglePause)
+                })?
+            },
+            {
+                let on_action = on_action.clone();
+                let input = speed.clone();
+                listen(&speed, "input", move |_| {
+                    on_action(PanelAction::Speed(input.value_as_number() as f32));
+                })?
+            },
+        ];
+        canvas.after_with_node_1(&root)?;
+
+        let panel = Self {
+            document,
+            root,
+            pause,
+            speed,
+            speed_text,
+            param_rows,
+            params: Vec::new(),
+            on_action,
+            listeners,
+            param_listeners: Vec::new(),
+        };
+        panel.place(canvas);
+        Ok(panel)
+    }
+
+    /// Moves the panel to the top left corner of `canvas`.
+    pub fn place(&self, canvas: &HtmlCanvasElement) {
+        let style = self.root.style();
+        let left = style.set_property("left", &format!("{}px", canvas.offset_left()));
+        let top = style.set_property("top", &format!("{}px", canvas.offset_top()));
+        if let Err(error) = left.and(top) {
+            gl::error!("Can not place controls {error:?}");
+        }
+    }
+
+    pub fn show_playback(&self, paused: bool, speed: f32) {
+        self.pause
+            .set_inner_text(if paused { "Play" } else { "Pause" });
+        self.speed.set_value(&speed.to_string());
+        self.speed_text.set_inner_text(&speed.to_string());
+    }
+
+    /// Replaces parameter rows with controls of `params`.
+    pub fn set_params(
+        &mut self,
+        params: Vec<ShaderParam>,
+        values: Option<&ParamValues>,
+    ) -> Result<(), JsValue> {
+        self.params = params;
+        self.show_values(values)
+    }
+
+    /// Builds parameter rows again, so controls show `values`.
+    pub fn show_values(&mut self, values: Option<&ParamValues>) -> Result<(), JsValue> {
+        self.param_rows.replace_children_with_node_0();
+        self.param_listeners.clear();
+        for param in &self.params {
+            let value = values
+                .and_then(|values| values.get(&param.name))
+                .and_then(|value| value.components(param.param_type))
+                .unwrap_or_else(|| param.default.clone());
+            let row = param_row(
+                &self.document,
+                &self.on_action,
+                param,
+                &value,
+                &mut self.param_listeners,
+            )?;
+            self.param_rows.append_with_node_1(&row)?;
+        }
+        Ok(())
+    }
+}
+
+/// Label with the name of `param` and its control, which shows `value`.
+fn param_row(
+    document: &Document,
+    on_action: &Rc<dyn Fn(PanelAction)>,
+    param: &ShaderParam,
+    value: &[f32],
+    listeners: &mut Vec<Listener>,
+) -> Result<HtmlElement, JsValue> {
+    let row = create::<HtmlElement>(document, "label")?;
+    row.set_class_name("shader-controls-param");
+    row.set_inner_text(&format!("{} ", param.name));
+    let integer = matches!(param.param_type, ParamType::Int | ParamType::Bool);
+    let name = param.name.clone();
+    let on_action = on_action.clone();
+
+    match param.control {
+        ParamControl::Slider { min, max, step } => {
+            let input = create_input(document, "range")?;
+            input.set_min(&min.to_string());
+            input.set_max(&max.to_string());
+            let step = step.map_or_else(
+                || (if integer { "1" } else { "any" }).to_owned(),
+                |step| step.to_string(),
+            );
+            input.set_step(&step);
+            input.set_value(&value[0].to_string());
+            let output = create::<HtmlElement>(document, "output")?;
+            output.set_inner_text(&value[0].to_string());
+            row.append_with_node_1(&input)?;
+            row.append_with_node_1(&output)?;
+            let source = input.clone();
+            listeners.push(listen(&input, "input", move |_| {
+                let value = source.value_as_number() as f32;
+                output.set_inner_text(&value.to_string());
+                on_action(PanelAction::Param(name.clone(), ParamValue::Number(value)));
+            })?);
+        }
+        ParamControl::Color => {
+            let input = create_input(document, "color")?;
+            input.set_value(&color_hex(value));
+            row.append_with_node_1(&input)?;
+            let source = input.clone();
+            listeners.push(listen(&input, "input", move |_| {
+                on_action(PanelAction::Param(
+                    name.clone(),
+                    ParamValue::Color(source.value()),
+                ));
+            })?);
+        }
+        ParamControl::Checkbox => {
+            let input = create_input(document, "checkbox")?;
+            input.set_checked(value[0] != 0.0);
+            row.append_with_node_1(&input)?;
+            let source = input.clone();
+            listeners.push(listen(&input, "change", move |_| {
+                on_action(PanelAction::Param(
+                    name.clone(),
+                    ParamValue::Bool(source.checked()),
+                ));
+            })?);
+        }
+        ParamControl::Input => {
+            let inputs = value
+                .iter()
+                .map(|component| {
+                    let input = create_input(document, "number")?;
+                    input.set_step(if integer { "1" } else { "any" });
+                    input.set_value(&component.to_string());
+                    row.append_with_node_1(&input)?;
+                    Ok(input)
+                })
+                .collect::<Result<Vec<_>, JsValue>>()?;
+            // Every field sends the whole value, so a vector is never half updated
+            for input in &inputs {
+                let (name, on_action, fields) = (name.clone(), on_action.clone(), inputs.clone());
+                listeners.push(listen(input, "change", move |_| {
+                    let components: Vec<f32> = fields
+                        .iter()
+                        .map(|input| input.value_as_number() as f32)
+                        .filter(|component| component.is_finite())
+                        .collect();
+                    if components.len() != fields.len() {
+                        return;
+                    }
+                    let value = if components.len() == 1 {
+                        ParamValue::Number(components[0])
+                    } else {
+                        ParamValue::Vector(components)
+                    };
+                    on_action(PanelAction::Param(name.clone(), value));
+                })?);
+            }
+        }
+    }
+    Ok(row)
+}
+
+impl Drop for ControlPanel {
+    fn drop(&mut self) {
+        self.root.remove();
+        // Listeners are dropped after the panel left the page
+        self.listeners.clear();
+        self.param_listeners.clear();
+    }
+}
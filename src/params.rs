@@ -0,0 +1,439 @@
+//! Custom uniforms declared by shaders, with hints for controls which edit them.
+//!
+//! A parameter is a uniform with annotations in a trailing comment, or one declared by pragma:
+//!
+//! ```glsl
+//! uniform float u_speed; // @slider(0, 10, 0.1) @default(1)
+//! #pragma param color u_tint @default(1, 0.5, 0)
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// GLSL type of a parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamType {
+    Float,
+    Int,
+    Bool,
+    Vec2,
+    Vec3,
+    Vec4,
+}
+
+impl ParamType {
+    fn from_glsl(name: &str) -> Option<Self> {
+        match name {
+            "float" => Some(ParamType::Float),
+            "int" => Some(ParamType::Int),
+            "bool" => Some(ParamType::Bool),
+            "vec2" => Some(ParamType::Vec2),
+            "vec3" => Some(ParamType::Vec3),
+            "vec4" => Some(ParamType::Vec4),
+            _ => None,
+        }
+    }
+
+    pub fn glsl_name(self) -> &'static str {
+        match self {
+            ParamType::Float => "float",
+            ParamType::Int => "int",
+            ParamType::Bool => "bool",
+            ParamType::Vec2 => "vec2",
+            ParamType::Vec3 => "vec3",
+            ParamType::Vec4 => "vec4",
+        }
+    }
+
+    pub fn components(self) -> usize {
+        match self {
+            ParamType::Float | ParamType::Int | ParamType::Bool => 1,
+            ParamType::Vec2 => 2,
+            ParamType::Vec3 => 3,
+            ParamType::Vec4 => 4,
+        }
+    }
+}
+
+/// Control suggested for editing a parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ParamControl {
+    /// `@slider(min, max, step)`, step is optional
+    Slider {
+        min: f32,
+        max: f32,
+        step: Option<f32>,
+    },
+    /// `@color` on `vec3` or `vec4`, components are in 0..1
+    Color,
+    /// `bool` parameters, `@checkbox` marks them without other annotations
+    Checkbox,
+    /// Number fields, one per component
+    Input,
+}
+
+/// Custom uniform, which value is set with `update_player_state({ custom })`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ShaderParam {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub param_type: ParamType,
+    pub control: ParamControl,
+    /// Value till it is set from JS, one number per component
+    pub default: Vec<f32>,
+}
+
+/// Parameter found on a line of code.
+pub struct ParsedParam {
+    pub param: ShaderParam,
+    /// Declared by `#pragma param`, so the runner declares the uniform
+    pub pragma: bool,
+}
+
+/// Names of known annotations, other words after `@` are left to comments.
+const ANNOTATIONS: [&str; 5] = ["param", "slider", "color", "checkbox", "default"];
+
+/// Annotations like `@slider(0, 1)`, with arguments parsed as numbers.
+fn annotations(text: &str) -> Vec<(&str, Vec<f32>)> {
+    text.split('@')
+        .skip(1)
+        .filter_map(|annotation| {
+            let name_end = annotation
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(annotation.len());
+            let (name, rest) = annotation.split_at(name_end);
+            let args = match rest.trim_start().strip_prefix('(') {
+                Some(rest) => rest
+                    .split_once(')')?
+                    .0
+                    .split(',')
+                    .filter_map(|arg| match arg.trim() {
+                        "true" => Some(1.0),
+                        "false" => Some(0.0),
+                        arg => arg.parse().ok(),
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+            ANNOTATIONS.contains(&name).then_some((name, args))
+        })
+        .collect()
+}
+
+fn param(param_type: ParamType, name: &str, annotations: &[(&str, Vec<f32>)]) -> ShaderParam {
+    let find = |wanted: &str| {
+        annotations
+            .iter()
+            .find(|(name, _)| *name == wanted)
+            .map(|(_, args)| args.as_slice())
+    };
+    let is_vector = matches!(param_type, ParamType::Vec3 | ParamType::Vec4);
+    let control = match find("slider") {
+        Some(&[min, max, ref rest @ ..]) if param_type.components() == 1 && rest.len() <= 1 => {
+            ParamControl::Slider {
+                min,
+                max,
+                step: rest.first().copied(),
+            }
+        }
+        _ if is_vector && find("color").is_some() => ParamControl::Color,
+        _ if param_type == ParamType::Bool => ParamControl::Checkbox,
+        _ => ParamControl::Input,
+    };
+    let default = match (find("default"), control) {
+        (Some(values), _) if values.len() == param_type.components() => values.to_vec(),
+        (_, ParamControl::Slider { min, .. }) => vec![min],
+        _ => vec![0.0; param_type.components()],
+    };
+    ShaderParam {
+        name: name.to_owned(),
+        param_type,
+        control,
+        default,
+    }
+}
+
+/// Name is a GLSL identifier, so it can be declared in generated code.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Finds a parameter declared on `line`: annotated uniform or `#pragma param <type> <name>`.
+///
+/// Type `color` of pragma is `vec3` with `@color`. Pragma may end with `;` after the name,
+/// pragmas with invalid names are ignored.
+pub fn parse_param(line: &str) -> Option<ParsedParam> {
+    let line = line.trim();
+    if let Some(rest) = line.strip_prefix('#') {
+        let rest = rest.trim_start().strip_prefix("pragma")?.trim_start();
+        let mut words = rest.strip_prefix("param")?.split_whitespace();
+        let (type_name, name) = (words.next()?, words.next()?);
+        let name = name.strip_suffix(';').unwrap_or(name);
+        if !is_identifier(name) {
+            return None;
+        }
+        let mut annotations = annotations(rest);
+        let param_type = if type_name == "color" {
+            annotations.push(("color", Vec::new()));
+            ParamType::Vec3
+        } else {
+            ParamType::from_glsl(type_name)?
+        };
+        return Some(ParsedParam {
+            param: param(param_type, name, &annotations),
+            pragma: true,
+        });
+    }
+
+    let (code, comment) = line.split_once("//")?;
+    let annotations = annotations(comment);
+    if annotations.is_empty() {
+        return None;
+    }
+    let mut words = code
+        .trim()
+        .strip_suffix(';')?
+        .split_whitespace()
+        .filter(|word| !matches!(*word, "lowp" | "mediump" | "highp"));
+    if words.next()? != "uniform" {
+        return None;
+    }
+    let param_type = ParamType::from_glsl(words.next()?)?;
+    let name = words.next()?;
+    (is_identifier(name) && words.next().is_none()).then(|| ParsedParam {
+        param: param(param_type, name, &annotations),
+        pragma: false,
+    })
+}
+
+/// Value of a parameter from JS: number, boolean, array of numbers or `"#rrggbb"` color.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ParamValue {
+    Bool(bool),
+    Number(f32),
+    Vector(Vec<f32>),
+    Color(String),
+}
+
+/// Values of parameters by name.
+pub type ParamValues = BTreeMap<String, ParamValue>;
+
+impl ParamValue {
+    /// Parses `#rgb`, `#rrggbb` or `#rrggbbaa` into components in 0..1.
+    fn color_components(hex: &str) -> Option<Vec<f32>> {
+        let hex = hex.strip_prefix('#')?;
+        if !hex.is_ascii() {
+            return None;
+        }
+        let digits = match hex.len() {
+            3 => 1,
+            6 | 8 => 2,
+            _ => return None,
+        };
+        (0..hex.len())
+            .step_by(digits)
+            .map(|start| {
+                let value = u8::from_str_radix(&hex[start..start + digits], 16).ok()?;
+                let max = if digits == 1 { 15.0 } else { 255.0 };
+                Some(f32::from(value) / max)
+            })
+            .collect()
+    }
+
+    /// Checks value which is received from JS, before type of parameter is known.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ParamValue::Vector(values) if values.is_empty() || values.len() > 4 => Err(format!(
+                "Parameter value must have 1 to 4 components, got {}",
+                values.len()
+            )),
+            ParamValue::Color(hex) if Self::color_components(hex).is_none() => Err(format!(
+                "Parameter color must be #rgb, #rrggbb or #rrggbbaa, got {hex:?}"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Components to upload for `param_type`, `None` if value doesn't fit it.
+    pub fn components(&self, param_type: ParamType) -> Option<Vec<f32>> {
+        let mut components = match self {
+            ParamValue::Bool(value) => vec![f32::from(u8::from(*value))],
+            ParamValue::Number(value) => vec![*value],
+            ParamValue::Vector(values) => values.clone(),
+            ParamValue::Color(hex) => Self::color_components(hex)?,
+        };
+        // Colors without alpha are opaque
+        if param_type == ParamType::Vec4 && components.len() == 3 {
+            components.push(1.0);
+        }
+        (components.len() == param_type.components()).then_some(components)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> (ShaderParam, bool) {
+        let parsed = parse_param(line).expect("line declares a parameter");
+        (parsed.param, parsed.pragma)
+    }
+
+    #[test]
+    fn annotated_uniform() {
+        let (param, pragma) =
+            parse("uniform highp float u_speed; // @slider(0, 10, 0.5) @default(2)");
+        assert!(!pragma);
+        assert_eq!(
+            param,
+            ShaderParam {
+                name: "u_speed".to_owned(),
+                param_type: ParamType::Float,
+                control: ParamControl::Slider {
+                    min: 0.0,
+                    max: 10.0,
+                    step: Some(0.5),
+                },
+                default: vec![2.0],
+            }
+        );
+    }
+
+    #[test]
+    fn defaults_follow_control() {
+        let (slider, _) = parse("uniform int u_count; // @slider(3, 9)");
+        assert_eq!(
+            slider.control,
+            ParamControl::Slider {
+                min: 3.0,
+                max: 9.0,
+                step: None
+            }
+        );
+        assert_eq!(slider.default, [3.0]);
+
+        let (checkbox, _) = parse("uniform bool u_on; // @default(true)");
+        assert_eq!(checkbox.control, ParamControl::Checkbox);
+        assert_eq!(checkbox.default, [1.0]);
+
+        // Default with a wrong number of components is ignored
+        let (input, _) = parse("uniform vec2 u_offset; // @param @default(1, 2, 3)");
+        assert_eq!(input.control, ParamControl::Input);
+        assert_eq!(input.default, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn color_annotation_needs_vector() {
+        let (color, _) = parse("uniform vec4 u_tint; // @color @default(1, 0.5, 0, 1)");
+        assert_eq!(color.control, ParamControl::Color);
+        assert_eq!(color.default, [1.0, 0.5, 0.0, 1.0]);
+
+        let (scalar, _) = parse("uniform float u_tint; // @color");
+        assert_eq!(scalar.control, ParamControl::Input);
+    }
+
+    #[test]
+    fn lines_without_parameters() {
+        assert!(parse_param("uniform float u_plain;").is_none());
+        assert!(parse_param("uniform float u_a; // @unknown(1)").is_none());
+        assert!(parse_param("uniform mat2 u_m; // @param").is_none());
+        assert!(parse_param("uniform float u_a, u_b; // @param").is_none());
+        assert!(parse_param("float x = 1.0; // @param").is_none());
+        assert!(parse_param("#pragma once").is_none());
+    }
+
+    #[test]
+    fn pragma_param() {
+        let (param, pragma) = parse("  # pragma param float u_zoom @slider(1, 4) @default(2)");
+        assert!(pragma);
+        assert_eq!(param.name, "u_zoom");
+        assert_eq!(param.param_type, ParamType::Float);
+        assert_eq!(param.default, [2.0]);
+
+        let (color, _) = parse("#pragma param color u_tint @default(1, 0.5, 0)");
+        assert_eq!(color.param_type, ParamType::Vec3);
+        assert_eq!(color.control, ParamControl::Color);
+        assert_eq!(color.default, [1.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn pragma_param_with_semicolon() {
+        let (param, _) = parse("#pragma param float u_gain; // @default(0.5)");
+        assert_eq!(param.name, "u_gain");
+        assert_eq!(param.default, [0.5]);
+        assert!(parse_param("#pragma param float ;").is_none());
+    }
+
+    #[test]
+    fn pragma_param_with_invalid_name() {
+        assert!(parse_param("#pragma param float 1st").is_none());
+        assert!(parse_param("#pragma param float u-gain").is_none());
+        assert!(parse_param("#pragma param float u_gain;;").is_none());
+        assert!(parse_param("#pragma param mat3 u_m").is_none());
+    }
+
+    #[test]
+    fn value_components() {
+        assert_eq!(
+            ParamValue::Bool(true).components(ParamType::Bool),
+            Some(vec![1.0])
+        );
+        assert_eq!(
+            ParamValue::Number(2.5).components(ParamType::Float),
+            Some(vec![2.5])
+        );
+        assert_eq!(ParamValue::Number(2.5).components(ParamType::Vec2), None);
+        assert_eq!(
+            ParamValue::Vector(vec![1.0, 2.0]).components(ParamType::Vec2),
+            Some(vec![1.0, 2.0])
+        );
+        // Three components are an opaque color for `vec4`
+        assert_eq!(
+            ParamValue::Vector(vec![0.1, 0.2, 0.3]).components(ParamType::Vec4),
+            Some(vec![0.1, 0.2, 0.3, 1.0])
+        );
+        assert_eq!(
+            ParamValue::Color("#ff0000".to_owned()).components(ParamType::Vec3),
+            Some(vec![1.0, 0.0, 0.0])
+        );
+        assert_eq!(
+            ParamValue::Color("#f00".to_owned()).components(ParamType::Vec4),
+            Some(vec![1.0, 0.0, 0.0, 1.0])
+        );
+        assert_eq!(
+            ParamValue::Color("#00ff0000".to_owned()).components(ParamType::Vec4),
+            Some(vec![0.0, 1.0, 0.0, 0.0])
+        );
+    }
+
+    #[test]
+    fn value_validation() {
+        assert!(ParamValue::Number(1.0).validate().is_ok());
+        assert!(ParamValue::Vector(vec![1.0; 4]).validate().is_ok());
+        assert!(ParamValue::Vector(Vec::new()).validate().is_err());
+        assert!(ParamValue::Vector(vec![1.0; 5]).validate().is_err());
+        assert!(ParamValue::Color("#abc".to_owned()).validate().is_ok());
+        assert!(ParamValue::Color("abc".to_owned()).validate().is_err());
+        assert!(ParamValue::Color("#abcd".to_owned()).validate().is_err());
+        assert!(ParamValue::Color("#ggg".to_owned()).validate().is_err());
+        assert!(ParamValue::Color("#ééé".to_owned()).validate().is_err());
+    }
+
+    #[test]
+    fn value_from_json() {
+        let values: ParamValues =
+            serde_json::from_str(r##"{ "a": true, "b": 0.5, "c": [1, 2], "d": "#ffffff" }"##)
+                .unwrap();
+        assert_eq!(values["a"], ParamValue::Bool(true));
+        assert_eq!(values["b"], ParamValue::Number(0.5));
+        assert_eq!(values["c"], ParamValue::Vector(vec![1.0, 2.0]));
+        assert_eq!(values["d"], ParamValue::Color("#ffffff".to_owned()));
+    }
+}
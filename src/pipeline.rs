@@ -0,0 +1,865 @@
+//! GL resources of a compiled project: programs of all passes and offscreen buffers between them.
+
+use crate::{
+    error::{info_log_details, ErrorDetail, ErrorKind},
+    events::PassUniforms,
+    include::ShaderLibrary,
+    params::{ParamType, ParamValues, ShaderParam},
+    pointer::TOUCH_COUNT,
+    program::{start_program, PendingProgram, ProgramError, ProgramStage},
+    project::{BufferId, ChannelInput, PassSource, Project, CHANNEL_COUNT},
+    shader::{
+        prepare_shader, PassKind, PreparedShader, SourceMap, UniformNames, CHANNEL_NAMES,
+        CHANNEL_RESOLUTION_NAME, TOUCH_NAME,
+    },
+    texture::ChannelTextures,
+};
+use core::fmt;
+use minwebgl as gl;
+use std::{
+    collections::BTreeMap,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+use web_sys::{
+    WebGl2RenderingContext as GL, WebGlFramebuffer, WebGlProgram, WebGlTexture,
+    WebGlUniformLocation,
+};
+
+/// Locations of built-in uniforms in linked program, `None` if uniform is unused.
+pub struct UniformLocations {
+    resolution: Option<WebGlUniformLocation>,
+    time: Option<WebGlUniformLocation>,
+    time_delta: Option<WebGlUniformLocation>,
+    frame: Option<WebGlUniformLocation>,
+    frame_rate: Option<WebGlUniformLocation>,
+    mouse: Option<WebGlUniformLocation>,
+    date: Option<WebGlUniformLocation>,
+    touch: Option<WebGlUniformLocation>,
+}
+
+impl UniformLocations {
+    fn new(gl: &GL, program: &WebGlProgram, names: &UniformNames) -> Self {
+        Self {
+            resolution: gl.get_uniform_location(program, names.resolution),
+            time: gl.get_uniform_location(program, names.time),
+            time_delta: gl.get_uniform_location(program, names.time_delta),
+            frame: gl.get_uniform_location(program, names.frame),
+            frame_rate: gl.get_uniform_location(program, names.frame_rate),
+            mouse: gl.get_uniform_location(program, names.mouse),
+            date: gl.get_uniform_location(program, names.date),
+            touch: gl.get_uniform_location(program, TOUCH_NAME),
+        }
+    }
+
+    /// Program uses one of uniforms, which change every frame.
+    fn uses_time(&self) -> bool {
+        self.time.is_some()
+            || self.time_delta.is_some()
+            || self.frame.is_some()
+            || self.frame_rate.is_some()
+            || self.date.is_some()
+    }
+}
+
+/// Values of built-in uniforms, which are the same for all passes of a frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameUniforms {
+    pub resolution: [f32; 3],
+    pub time: f32,
+    pub time_delta: f32,
+    pub frame: i32,
+    pub frame_rate: f32,
+    /// Left untouched until mouse is used or set
+    pub mouse: Option<[f32; 4]>,
+    pub date: [f32; 4],
+    /// Pressed pointers, see `Pointers::uniform()`
+    pub touches: [[f32; 4]; TOUCH_COUNT],
+}
+
+impl FrameUniforms {
+    fn apply(&self, gl: &GL, locations: &UniformLocations) {
+        let [width, height, pixel_aspect_ratio] = self.resolution;
+        gl.uniform3f(
+            locations.resolution.as_ref(),
+            width,
+            height,
+            pixel_aspect_ratio,
+        );
+        gl.uniform1f(locations.time.as_ref(), self.time);
+        gl.uniform1f(locations.time_delta.as_ref(), self.time_delta);
+        gl.uniform1i(locations.frame.as_ref(), self.frame);
+        gl.uniform1f(locations.frame_rate.as_ref(), self.frame_rate);
+        if let Some([x, y, down_x, down_y]) = self.mouse {
+            gl.uniform4f(locations.mouse.as_ref(), x, y, down_x, down_y);
+        }
+        let [year, month, day, time] = self.date;
+        gl.uniform4f(locations.date.as_ref(), year, month, day, time);
+        gl.uniform4fv_with_f32_array(locations.touch.as_ref(), self.touches.as_flattened());
+    }
+}
+
+/// Names of active uniforms of a linked program.
+fn active_uniform_names(gl: &GL, program: &WebGlProgram) -> Vec<String> {
+    let count = gl
+        .get_program_parameter(program, GL::ACTIVE_UNIFORMS)
+        .as_f64()
+        .unwrap_or(0.0) as u32;
+    (0..count)
+        .filter_map(|index| gl.get_active_uniform(program, index))
+        .map(|info| info.name())
+        .collect()
+}
+
+/// Failed compilation of one of passes.
+#[derive(Debug)]
+pub struct CompileError {
+    /// `Image` or `Buffer A`..`Buffer D`
+    pub pass: String,
+    pub error: ProgramError,
+    /// Map of the prepared fragment shader
+    pub source_map: SourceMap,
+}
+
+impl CompileError {
+    /// Errors of the info log with lines of user code.
+    pub fn details(&self) -> Vec<ErrorDetail> {
+        let (kind, source_map) = match self.error.stage {
+            ProgramStage::FragmentShader => (ErrorKind::Compile, Some(&self.source_map)),
+            ProgramStage::VertexShader => (ErrorKind::Compile, None),
+            ProgramStage::Link => (ErrorKind::Link, None),
+        };
+        info_log_details(&self.pass, kind, &self.error.log, source_map)
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.pass, self.error)
+    }
+}
+
+/// Number of linked programs kept by `ProgramCache`
+const PROGRAM_CACHE_SIZE: usize = 16;
+
+/// Linked program of a pass with its bindings.
+struct Pass {
+    program: WebGlProgram,
+    /// Hash of sources the program is linked from, see `source_key()`
+    key: u64,
+    uniforms: UniformLocations,
+    channels: [Option<WebGlUniformLocation>; CHANNEL_COUNT],
+    channel_resolution: Option<WebGlUniformLocation>,
+    inputs: [Option<ChannelInput>; CHANNEL_COUNT],
+    /// Custom uniforms declared by the shader, `None` location if uniform is unused
+    params: Vec<(ShaderParam, Option<WebGlUniformLocation>)>,
+}
+
+/// Key of a program in `ProgramCache`.
+fn source_key(vertex_shader_src: &str, fragment_shader_src: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    vertex_shader_src.hash(&mut hasher);
+    fragment_shader_src.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Linked passes of recent projects, which are not drawn now, least recently used first.
+///
+/// Setting shader seen before takes its program from here instead of compiling it again.
+#[derive(Default)]
+pub struct ProgramCache {
+    passes: Vec<Pass>,
+}
+
+impl ProgramCache {
+    fn take(&mut self, key: u64) -> Option<Pass> {
+        let index = self.passes.iter().position(|pass| pass.key == key)?;
+        Some(self.passes.remove(index))
+    }
+
+    /// Keeps `pass` as the most recent one, the least recent passes above capacity are deleted.
+    fn put(&mut self, gl: &GL, pass: Pass) {
+        // Two passes of a project may have the same code
+        if let Some(same) = self.take(pass.key) {
+            gl.delete_program(Some(&same.program));
+        }
+        self.passes.push(pass);
+        let excess = self.passes.len().saturating_sub(PROGRAM_CACHE_SIZE);
+        for pass in self.passes.drain(..excess) {
+            gl.delete_program(Some(&pass.program));
+        }
+    }
+
+    /// Forgets all programs without deleting, as they are gone together with lost context.
+    pub fn clear(&mut self) {
+        self.passes.clear();
+    }
+
+    pub fn delete(&mut self, gl: &GL) {
+        for pass in self.passes.drain(..) {
+            gl.delete_program(Some(&pass.program));
+        }
+    }
+}
+
+/// Pass, which program is being compiled or is taken from `ProgramCache`.
+enum PendingPass {
+    Cached(Pass),
+    Compiling {
+        program: PendingProgram,
+        key: u64,
+        fragment_shader: PreparedShader,
+        inputs: [Option<ChannelInput>; CHANNEL_COUNT],
+    },
+}
+
+impl PendingPass {
+    fn is_ready(&self, gl: &GL, parallel: bool) -> bool {
+        match self {
+            PendingPass::Cached(_) => true,
+            PendingPass::Compiling { program, .. } => program.is_ready(gl, parallel),
+        }
+    }
+
+    fn finish(self, gl: &GL) -> Result<Pass, (ProgramError, SourceMap)> {
+        let (program, key, fragment_shader, inputs) = match self {
+            PendingPass::Cached(pass) => return Ok(pass),
+            PendingPass::Compiling {
+                program,
+                key,
+                fragment_shader,
+                inputs,
+            } => (program, key, fragment_shader, inputs),
+        };
+        let program = match program.finish(gl) {
+            Ok(program) => program,
+            Err(error) => return Err((error, fragment_shader.source_map)),
+        };
+        let uniforms = UniformLocations::new(gl, &program, fragment_shader.dialect.naming.names());
+        let channels = CHANNEL_NAMES.map(|name| gl.get_uniform_location(&program, name));
+        let channel_resolution = gl.get_uniform_location(&program, CHANNEL_RESOLUTION_NAME);
+        let params = fragment_shader
+            .params
+            .into_iter()
+            .map(|param| {
+                let location = gl.get_uniform_location(&program, &param.name);
+                (param, location)
+            })
+            .collect();
+
+        Ok(Pass {
+            program,
+            key,
+            uniforms,
+            channels,
+            channel_resolution,
+            inputs,
+            params,
+        })
+    }
+
+    /// Returns cached program back to cache and stops compilation of others.
+    fn discard(self, gl: &GL, cache: &mut ProgramCache) {
+        match self {
+            PendingPass::Cached(pass) => cache.put(gl, pass),
+            PendingPass::Compiling { program, .. } => program.delete(gl),
+        }
+    }
+}
+
+impl Pass {
+    fn start(
+        gl: &GL,
+        vertex_shader_src: &str,
+        common: &str,
+        source: &PassSource,
+        kind: PassKind,
+        library: &ShaderLibrary,
+        cache: &mut ProgramCache,
+    ) -> Result<PendingPass, (ProgramError, SourceMap)> {
+        let fragment_shader = prepare_shader(common, &source.code, kind, library);
+        let key = source_key(vertex_shader_src, &fragment_shader.source);
+        let inputs = core::array::from_fn(|index| source.channel(index));
+        if let Some(pass) = cache.take(key) {
+            return Ok(PendingPass::Cached(Pass { inputs, ..pass }));
+        }
+        match start_program(gl, vertex_shader_src, &fragment_shader.source) {
+            Ok(program) => Ok(PendingPass::Compiling {
+                program,
+                key,
+                fragment_shader,
+                inputs,
+            }),
+            Err(error) => Err((error, fragment_shader.source_map)),
+        }
+    }
+
+    /// Pass reads a buffer, which is not rendered yet on this frame, so its output evolves.
+    fn reads_previous_frame(&self, id: BufferId) -> bool {
+        self.inputs
+            .iter()
+            .any(|input| matches!(input, Some(ChannelInput::Buffer(buffer)) if *buffer >= id))
+    }
+
+    /// Uploads custom uniforms, parameters without a fitting value get their defaults.
+    fn apply_params(&self, gl: &GL, values: Option<&ParamValues>) {
+        if self.params.iter().all(|(_, location)| location.is_none()) {
+            return;
+        }
+        gl.use_program(Some(&self.program));
+        for (param, location) in &self.params {
+            let Some(location) = location else {
+                continue;
+            };
+            let value = values
+                .and_then(|values| values.get(&param.name))
+                .and_then(|value| value.components(param.param_type))
+                .unwrap_or_else(|| param.default.clone());
+            match param.param_type {
+                ParamType::Float => gl.uniform1f(Some(location), value[0]),
+                ParamType::Int | ParamType::Bool => gl.uniform1i(Some(location), value[0] as i32),
+                ParamType::Vec2 => gl.uniform2fv_with_f32_array(Some(location), &value),
+                ParamType::Vec3 => gl.uniform3fv_with_f32_array(Some(location), &value),
+                ParamType::Vec4 => gl.uniform4fv_with_f32_array(Some(location), &value),
+            }
+        }
+    }
+
+    fn draw(&self, gl: &GL, uniforms: &FrameUniforms, inputs: &ChannelSources<'_>) {
+        gl.use_program(Some(&self.program));
+        uniforms.apply(gl, &self.uniforms);
+
+        let mut channel_resolution = [0f32; 3 * CHANNEL_COUNT];
+        for (unit, (location, input)) in self.channels.iter().zip(&self.inputs).enumerate() {
+            let (texture, resolution) = match input.and_then(|input| inputs.get(input)) {
+                Some((texture, resolution)) => (Some(texture), resolution),
+                None => (None, [0.0; 3]),
+            };
+            channel_resolution[unit * 3..unit * 3 + 3].copy_from_slice(&resolution);
+            gl.active_texture(GL::TEXTURE0 + unit as u32);
+            gl.bind_texture(GL::TEXTURE_2D, texture);
+            gl.uniform1i(location.as_ref(), unit as i32);
+        }
+        gl.uniform3fv_with_f32_array(self.channel_resolution.as_ref(), &channel_resolution);
+
+        gl.draw_arrays(GL::TRIANGLE_STRIP, 0, 4);
+    }
+}
+
+/// Everything a channel can read during a frame.
+struct ChannelSources<'a> {
+    targets: &'a BufferTargets,
+    textures: &'a ChannelTextures,
+}
+
+impl ChannelSources<'_> {
+    fn get(&self, input: ChannelInput) -> Option<(&WebGlTexture, [f32; 3])> {
+        match input {
+            ChannelInput::Buffer(id) => {
+                let (width, height) = self.targets.size;
+                let target = self.targets.buffers.get(&id)?;
+                Some((target.read_texture(), [width as f32, height as f32, 1.0]))
+            }
+            ChannelInput::Texture(slot) => self.textures.get(slot),
+            ChannelInput::Keyboard => self.textures.keyboard.get(),
+        }
+    }
+}
+
+/// Pair of textures of a buffer, passes read one of them while the other is rendered to.
+struct PingPong {
+    textures: [WebGlTexture; 2],
+    framebuffers: [WebGlFramebuffer; 2],
+    read: usize,
+}
+
+impl PingPong {
+    /// Returns `None` if context is lost.
+    fn new(gl: &GL, internal_format: u32, width: i32, height: i32) -> Option<Self> {
+        let textures = [gl.create_texture()?, gl.create_texture()?];
+        let framebuffers = [gl.create_framebuffer()?, gl.create_framebuffer()?];
+        for (texture, framebuffer) in textures.iter().zip(&framebuffers) {
+            gl.bind_texture(GL::TEXTURE_2D, Some(texture));
+            // Storage is zero-initialized, so feedback buffers start from black
+            gl.tex_storage_2d(GL::TEXTURE_2D, 1, internal_format, width, height);
+            gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+            gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+            gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+            gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+
+            gl.bind_framebuffer(GL::FRAMEBUFFER, Some(framebuffer));
+            gl.framebuffer_texture_2d(
+                GL::FRAMEBUFFER,
+                GL::COLOR_ATTACHMENT0,
+                GL::TEXTURE_2D,
+                Some(texture),
+                0,
+            );
+        }
+        gl.bind_texture(GL::TEXTURE_2D, None);
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+
+        Some(Self {
+            textures,
+            framebuffers,
+            read: 0,
+        })
+    }
+
+    fn read_texture(&self) -> &WebGlTexture {
+        &self.textures[self.read]
+    }
+
+    fn read_framebuffer(&self) -> &WebGlFramebuffer {
+        &self.framebuffers[self.read]
+    }
+
+    fn write_framebuffer(&self) -> &WebGlFramebuffer {
+        &self.framebuffers[1 - self.read]
+    }
+
+    fn swap(&mut self) {
+        self.read = 1 - self.read;
+    }
+
+    fn delete(&self, gl: &GL) {
+        for texture in &self.textures {
+            gl.delete_texture(Some(texture));
+        }
+        for framebuffer in &self.framebuffers {
+            gl.delete_framebuffer(Some(framebuffer));
+        }
+    }
+}
+
+/// Textures of all buffers, a pipeline can render into several sets of them.
+#[derive(Default)]
+pub struct BufferTargets {
+    buffers: BTreeMap<BufferId, PingPong>,
+    size: (i32, i32),
+}
+
+impl BufferTargets {
+    /// Recreates buffers for the new size, content of existing ones is stretched to it.
+    ///
+    /// Feedback passes continue from their state, so changes of adaptive scale don't restart them.
+    fn resize(
+        &mut self,
+        gl: &GL,
+        ids: impl Iterator<Item = BufferId>,
+        internal_format: u32,
+        (width, height): (i32, i32),
+    ) {
+        let previous = core::mem::take(&mut self.buffers);
+        let (previous_width, previous_height) = self.size;
+        for id in ids {
+            let Some(target) = PingPong::new(gl, internal_format, width, height) else {
+                continue;
+            };
+            if let Some(source) = previous.get(&id) {
+                gl.bind_framebuffer(GL::READ_FRAMEBUFFER, Some(source.read_framebuffer()));
+                gl.bind_framebuffer(GL::DRAW_FRAMEBUFFER, Some(target.read_framebuffer()));
+                gl.blit_framebuffer(
+                    0,
+                    0,
+                    previous_width,
+                    previous_height,
+                    0,
+                    0,
+                    width,
+                    height,
+                    GL::COLOR_BUFFER_BIT,
+                    GL::LINEAR,
+                );
+            }
+            self.buffers.insert(id, target);
+        }
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+        for target in previous.values() {
+            target.delete(gl);
+        }
+        self.size = (width, height);
+    }
+
+    /// Frees textures, buffers are created again by the next render.
+    pub fn delete(&mut self, gl: &GL) {
+        for target in self.buffers.values() {
+            target.delete(gl);
+        }
+        self.buffers.clear();
+        self.size = (0, 0);
+    }
+}
+
+/// Format of buffer textures, the best one supported by the context.
+fn buffer_format(gl: &GL) -> u32 {
+    let has_extension = |name| matches!(gl.get_extension(name), Ok(Some(_)));
+    if !has_extension("EXT_color_buffer_float") {
+        gl::info!(
+            "EXT_color_buffer_float is not supported, buffers are limited to 8 bits per channel"
+        );
+        GL::RGBA8
+    } else if has_extension("OES_texture_float_linear") {
+        GL::RGBA32F
+    } else {
+        // Half floats are filterable without extensions
+        GL::RGBA16F
+    }
+}
+
+/// Programs of all passes of a project.
+struct Passes {
+    buffers: BTreeMap<BufferId, Pass>,
+    image: Pass,
+    internal_format: u32,
+}
+
+impl Passes {
+    /// Frames differ only if inputs change: no pass uses time and there is no feedback.
+    fn is_static(&self) -> bool {
+        let buffers_static = self
+            .buffers
+            .iter()
+            .all(|(&id, pass)| !pass.uniforms.uses_time() && !pass.reads_previous_frame(id));
+        buffers_static && !self.image.uniforms.uses_time()
+    }
+
+    /// Some buffer reads its own or a later buffer, so a frame depends on the previous ones.
+    fn has_feedback(&self) -> bool {
+        self.buffers
+            .iter()
+            .any(|(&id, pass)| pass.reads_previous_frame(id))
+    }
+
+    fn render(
+        &self,
+        gl: &GL,
+        uniforms: &FrameUniforms,
+        textures: &ChannelTextures,
+        targets: &mut BufferTargets,
+        output: Option<&WebGlFramebuffer>,
+        size: (i32, i32),
+    ) {
+        if !self.buffers.is_empty() && targets.size != size {
+            targets.resize(gl, self.buffers.keys().copied(), self.internal_format, size);
+        }
+        gl.viewport(0, 0, size.0, size.1);
+
+        for (id, pass) in &self.buffers {
+            let Some(target) = targets.buffers.get(id) else {
+                continue;
+            };
+            gl.bind_framebuffer(GL::FRAMEBUFFER, Some(target.write_framebuffer()));
+            let inputs = ChannelSources { targets, textures };
+            pass.draw(gl, uniforms, &inputs);
+            if let Some(target) = targets.buffers.get_mut(id) {
+                target.swap();
+            }
+        }
+        self.render_image(gl, uniforms, textures, targets, output);
+    }
+
+    /// Renders only the image pass, which reads the current content of `targets`.
+    fn render_image(
+        &self,
+        gl: &GL,
+        uniforms: &FrameUniforms,
+        textures: &ChannelTextures,
+        targets: &BufferTargets,
+        output: Option<&WebGlFramebuffer>,
+    ) {
+        gl.bind_framebuffer(GL::FRAMEBUFFER, output);
+        let inputs = ChannelSources { targets, textures };
+        self.image.draw(gl, uniforms, &inputs);
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Pass> {
+        self.buffers.values().chain(core::iter::once(&self.image))
+    }
+
+    fn delete(&self, gl: &GL) {
+        for pass in self.iter() {
+            gl.delete_program(Some(&pass.program));
+        }
+    }
+
+    fn into_cache(self, gl: &GL, cache: &mut ProgramCache) {
+        for pass in self
+            .buffers
+            .into_values()
+            .chain(core::iter::once(self.image))
+        {
+            cache.put(gl, pass);
+        }
+    }
+}
+
+/// Project, which passes are being compiled, the current pipeline keeps rendering meanwhile.
+pub struct PendingPipeline {
+    buffers: BTreeMap<BufferId, PendingPass>,
+    image: PendingPass,
+    /// `KHR_parallel_shader_compile` is enabled, so completion can be polled
+    parallel: bool,
+}
+
+impl PendingPipeline {
+    fn passes(&self) -> impl Iterator<Item = &PendingPass> {
+        self.buffers.values().chain(core::iter::once(&self.image))
+    }
+
+    /// All passes are compiled and linked, so `finish()` doesn't block.
+    pub fn is_ready(&self, gl: &GL) -> bool {
+        self.passes().all(|pass| pass.is_ready(gl, self.parallel))
+    }
+
+    /// Takes compiled programs, error is reported for the first failed pass in render order.
+    ///
+    /// Passes which compiled are kept in `cache` on error, so fixing another pass doesn't recompile them.
+    pub fn finish(self, gl: &GL, cache: &mut ProgramCache) -> Result<Pipeline, CompileError> {
+        let mut first_error = None;
+        let buffers: BTreeMap<_, _> = self
+            .buffers
+            .into_iter()
+            .filter_map(|(id, pass)| match pass.finish(gl) {
+                Ok(pass) => Some((id, pass)),
+                Err((error, source_map)) => {
+                    first_error.get_or_insert(CompileError {
+                        pass: id.to_string(),
+                        error,
+                        source_map,
+                    });
+                    None
+                }
+            })
+            .collect();
+
+        let image = match self.image.finish(gl) {
+            Ok(pass) => pass,
+            Err((error, source_map)) => {
+                for pass in buffers.into_values() {
+                    cache.put(gl, pass);
+                }
+                return Err(first_error.unwrap_or(CompileError {
+                    pass: "Image".to_owned(),
+                    error,
+                    source_map,
+                }));
+            }
+        };
+        if let Some(error) = first_error {
+            for pass in buffers.into_values().chain(core::iter::once(image)) {
+                cache.put(gl, pass);
+            }
+            return Err(error);
+        }
+
+        Ok(Pipeline {
+            passes: Passes {
+                buffers,
+                image,
+                internal_format: buffer_format(gl),
+            },
+            targets: BufferTargets::default(),
+        })
+    }
+
+    /// Abandons compilation, when project is replaced or context is lost.
+    pub fn delete(self, gl: &GL, cache: &mut ProgramCache) {
+        self.buffers
+            .into_values()
+            .chain(core::iter::once(self.image))
+            .for_each(|pass| pass.discard(gl, cache));
+    }
+}
+
+/// Compiled project, which renders buffers and then the image pass.
+pub struct Pipeline {
+    passes: Passes,
+    targets: BufferTargets,
+}
+
+impl Pipeline {
+    /// Starts compilation of all passes, which goes on in background if driver supports it.
+    ///
+    /// Modules included by passes are taken from `library`, and passes with code seen recently
+    /// are taken from `cache`. Errors of shader code are known only after `PendingPipeline::finish()`.
+    pub fn start_compile(
+        gl: &GL,
+        vertex_shader_src: &str,
+        project: &Project,
+        library: &ShaderLibrary,
+        cache: &mut ProgramCache,
+    ) -> Result<PendingPipeline, CompileError> {
+        let parallel = matches!(gl.get_extension("KHR_parallel_shader_compile"), Ok(Some(_)));
+        let mut buffers = BTreeMap::new();
+        for (&id, source) in &project.buffers {
+            match Pass::start(
+                gl,
+                vertex_shader_src,
+                &project.common,
+                source,
+                PassKind::Buffer,
+                library,
+                cache,
+            ) {
+                Ok(pass) => {
+                    buffers.insert(id, pass);
+                }
+                Err((error, source_map)) => {
+                    buffers
+                        .into_values()
+                        .for_each(|pass| pass.discard(gl, cache));
+                    return Err(CompileError {
+                        pass: id.to_string(),
+                        error,
+                        source_map,
+                    });
+                }
+            }
+        }
+
+        let image = match Pass::start(
+            gl,
+            vertex_shader_src,
+            &project.common,
+            &project.image,
+            PassKind::Image,
+            library,
+            cache,
+        ) {
+            Ok(pass) => pass,
+            Err((error, source_map)) => {
+                buffers
+                    .into_values()
+                    .for_each(|pass| pass.discard(gl, cache));
+                return Err(CompileError {
+                    pass: "Image".to_owned(),
+                    error,
+                    source_map,
+                });
+            }
+        };
+
+        Ok(PendingPipeline {
+            buffers,
+            image,
+            parallel,
+        })
+    }
+
+    /// Renders all buffers and then the image into `output` of `size`, canvas if `None`.
+    pub fn draw(
+        &mut self,
+        gl: &GL,
+        uniforms: &FrameUniforms,
+        textures: &ChannelTextures,
+        output: Option<&WebGlFramebuffer>,
+        size: (i32, i32),
+    ) {
+        self.passes
+            .render(gl, uniforms, textures, &mut self.targets, output, size);
+    }
+
+    /// Renders a frame into `output` of `size`, using `targets` instead of buffers of the canvas.
+    ///
+    /// Frames shown on the canvas are not affected, so this is used for captures.
+    pub fn draw_offscreen(
+        &self,
+        gl: &GL,
+        uniforms: &FrameUniforms,
+        textures: &ChannelTextures,
+        targets: &mut BufferTargets,
+        output: &WebGlFramebuffer,
+        size: (i32, i32),
+    ) {
+        self.passes
+            .render(gl, uniforms, textures, targets, Some(output), size);
+    }
+
+    /// Renders only the image pass into `output` of `size`, over buffers of the last frame on the canvas.
+    ///
+    /// Buffers are stretched if `size` differs from theirs. Returns `false` without drawing
+    /// if the canvas has not rendered buffers yet.
+    pub fn draw_image_offscreen(
+        &self,
+        gl: &GL,
+        uniforms: &FrameUniforms,
+        textures: &ChannelTextures,
+        output: &WebGlFramebuffer,
+        size: (i32, i32),
+    ) -> bool {
+        if self.targets.buffers.len() != self.passes.buffers.len() {
+            return false;
+        }
+        gl.viewport(0, 0, size.0, size.1);
+        self.passes
+            .render_image(gl, uniforms, textures, &self.targets, Some(output));
+        true
+    }
+
+    /// Uniforms used by each pass, buffers first.
+    pub fn active_uniforms(&self, gl: &GL) -> Vec<PassUniforms> {
+        let buffers = self
+            .passes
+            .buffers
+            .iter()
+            .map(|(id, pass)| (id.to_string(), pass));
+        buffers
+            .chain(core::iter::once(("Image".to_owned(), &self.passes.image)))
+            .map(|(pass, program)| PassUniforms {
+                pass,
+                uniforms: active_uniform_names(gl, &program.program),
+            })
+            .collect()
+    }
+
+    /// Custom uniforms of all passes, buffers first, a name declared by several passes is listed once.
+    pub fn params(&self) -> Vec<ShaderParam> {
+        let mut params: Vec<ShaderParam> = Vec::new();
+        for (param, _) in self.passes.iter().flat_map(|pass| &pass.params) {
+            if !params.iter().any(|known| known.name == param.name) {
+                params.push(param.clone());
+            }
+        }
+        params
+    }
+
+    /// Sets values of custom uniforms, programs keep them till the next call.
+    pub fn apply_params(&self, gl: &GL, values: Option<&ParamValues>) {
+        for pass in self.passes.iter() {
+            pass.apply_params(gl, values);
+        }
+    }
+
+    /// Shader doesn't animate, so frames are rendered only when inputs change.
+    pub fn is_static(&self) -> bool {
+        self.passes.is_static()
+    }
+
+    /// Number of passes rendered per frame, buffers and the image.
+    pub fn pass_count(&self) -> usize {
+        self.passes.buffers.len() + 1
+    }
+
+    /// Buffers carry state between frames, so a frame can't be rendered without the previous ones.
+    pub fn has_feedback(&self) -> bool {
+        self.passes.has_feedback()
+    }
+
+    /// Clears buffers, so feedback passes start over as after `set_project()`.
+    pub fn reset_buffers(&mut self, gl: &GL) {
+        self.targets.delete(gl);
+    }
+
+    /// Frees all GL resources of the pipeline.
+    pub fn delete(mut self, gl: &GL) {
+        self.targets.delete(gl);
+        self.passes.delete(gl);
+    }
+
+    /// Frees buffers and keeps programs in `cache`, when pipeline is replaced by another one.
+    pub fn into_cache(mut self, gl: &GL, cache: &mut ProgramCache) {
+        self.targets.delete(gl);
+        self.passes.into_cache(gl, cache);
+    }
+}
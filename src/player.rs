@@ -0,0 +1,1683 @@
+//! Player bound to a single canvas, with its own GL context, state, listeners and render loop.
+
+use crate::{
+    adaptive::{AdaptiveOptions, ResolutionController, Upscaler},
+    capture::{
+        encode_png, CaptureFrames, CaptureOptions, FrameFormat, Offscreen, RecordingOptions,
+    },
+    error::PlayerError,
+    events::{FrameRendered, PlaybackChanged, PlayerEvent, ShaderCompileFailed, ShaderCompiled},
+    include::with_library,
+    panel::{ControlPanel, PanelAction},
+    pipeline::{FrameUniforms, PendingPipeline, Pipeline, ProgramCache},
+    pointer::Pointers,
+    project::{Project, CHANNEL_COUNT},
+    reject, report_error,
+    state::{
+        MouseMode, MouseUniform, Playback, PlaybackMode, PlayerState, PlayerStateSnapshot,
+        PlayerStateUpdate, ResolutionUniform, Uniforms,
+    },
+    stats::{FrameStats, GpuTimer, STATS_EVENT_INTERVAL},
+    texture::{decode_image, ChannelTexture, ChannelTextures, TextureOptions},
+};
+use js_sys::Date;
+use minwebgl as gl;
+use serde::Serialize;
+use std::{
+    cell::{Cell, Ref, RefCell},
+    collections::BTreeMap,
+    rc::{Rc, Weak},
+};
+use wasm_bindgen::{closure::Closure, prelude::wasm_bindgen, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    CanvasRenderingContext2d, Element, EventTarget, HtmlCanvasElement, HtmlElement,
+    IntersectionObserver, IntersectionObserverEntry, KeyboardEvent, MouseEvent, PointerEvent,
+    ResizeObserver, ResizeObserverEntry, WebGl2RenderingContext as GL,
+};
+
+const VERTEX_SHADER_SRC: &str = include_str!("../shaders/shader.vert");
+const DEFAULT_FRAGMENT_SHADER_SRC: &str = include_str!("../shaders/shader.frag");
+/// Frame rate of `step_frames()`, also relates `iFrame` to time after `seek()`
+const STEP_FRAME_RATE: f64 = 60.0;
+/// Fragments rendered by warmup of a screenshot, bounds the time the page is blocked by it
+const MAX_WARMUP_FRAGMENTS: u64 = 1 << 28;
+/// Weight of the latest frame in measured fps
+const FPS_SMOOTHING: f64 = 0.1;
+
+/// Listener of canvas or window events, kept to unsubscribe on `destroy()`.
+struct Listener {
+    target: EventTarget,
+    event_type: &'static str,
+    closure: Closure<dyn FnMut(web_sys::Event)>,
+}
+
+/// Callbacks of a promise returned by `set_project()`, settled when the project is compiled.
+struct CompileWaiter {
+    resolve: js_sys::Function,
+    reject: js_sys::Function,
+}
+
+/// Observer of canvas size, kept to disconnect on `destroy()`.
+struct SizeObserver {
+    observer: ResizeObserver,
+    _closure: Closure<dyn FnMut(js_sys::Array)>,
+}
+
+/// Observer of canvas intersection with the viewport, kept to disconnect on `destroy()`.
+struct VisibilityObserver {
+    observer: IntersectionObserver,
+    _closure: Closure<dyn FnMut(js_sys::Array)>,
+}
+
+/// GL resources and timing of the render loop.
+struct Renderer {
+    gl: GL,
+    pipeline: Option<Pipeline>,
+    /// Project compiled in background with real time the compilation started, see `KHR_parallel_shader_compile`
+    pending_pipeline: Option<(PendingPipeline, f64)>,
+    /// Programs of previous projects, so switching back to them doesn't compile again
+    program_cache: ProgramCache,
+    last_real_time: f64,
+    last_playback_time: f64,
+    frame: f32,
+    /// Incremented when pipeline is replaced, so captures notice it
+    pipeline_generation: u32,
+    /// Smoothed rate of rendered frames, zero while paused
+    fps: f64,
+    /// Uniforms of the last rendered frame
+    last_uniforms: FrameUniforms,
+    resolution: ResolutionController,
+    /// Created when adaptive resolution lowers render size for the first time
+    upscaler: Option<Upscaler>,
+    /// Exists while GPU timing is enabled
+    gpu_timer: Option<GpuTimer>,
+    /// Real time of the last `FrameStatsEvent`
+    last_stats_time: f64,
+    /// Events to dispatch after the frame
+    events: Vec<PlayerEvent>,
+    /// Custom uniforms were uploaded after the last drawn frame, so paused player draws again
+    params_changed: bool,
+    reload_webgl2_context: bool,
+}
+
+/// Jump of playback requested from JS, applied on the next frame.
+#[derive(Clone, Copy)]
+struct Seek {
+    time: f64,
+    reset_buffers: bool,
+}
+
+/// State shared between API calls, canvas listeners and the render loop.
+struct PlayerShared {
+    canvas: HtmlCanvasElement,
+    state: RefCell<PlayerState>,
+    project: RefCell<Project>,
+    reload_project: Cell<bool>,
+    /// Values of custom uniforms changed or pipeline was replaced, so they are uploaded again
+    params_changed: Cell<bool>,
+    /// Promises of `set_project()` calls waiting for the current project
+    compile_waiters: RefCell<Vec<CompileWaiter>>,
+    context_lost: Cell<bool>,
+    pointers: RefCell<Pointers>,
+    seek: Cell<Option<Seek>>,
+    /// Frames left to render while paused
+    pending_steps: Cell<u32>,
+    /// Input changed since the last frame, so on-demand playback renders the next one
+    redraw_requested: Cell<bool>,
+    channel_textures: RefCell<ChannelTextures>,
+    renderer: RefCell<Renderer>,
+    listeners: RefCell<Vec<Listener>>,
+    size_observer: RefCell<Option<SizeObserver>>,
+    /// CSS size of canvas, `None` till the first report of the observer
+    css_size: Cell<Option<(f64, f64)>>,
+    visibility_observer: RefCell<Option<VisibilityObserver>>,
+    /// Canvas is within the viewport, `true` till the first report of the observer
+    intersecting: Cell<bool>,
+    /// Rendering is suspended because canvas or page is hidden
+    suspended: Cell<bool>,
+    /// Controls overlay, exists while shown
+    panel: RefCell<Option<ControlPanel>>,
+    destroyed: Cell<bool>,
+}
+
+/// Shader player rendering to the given canvas, many players can live on the same page.
+#[wasm_bindgen]
+pub struct ShaderPlayer {
+    shared: Rc<PlayerShared>,
+}
+
+#[wasm_bindgen]
+impl ShaderPlayer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(canvas: HtmlCanvasElement) -> Result<ShaderPlayer, JsValue> {
+        Self::from_canvas(canvas).map_err(reject)
+    }
+
+    /// Resolves when the shader is compiled and drawn instead of the previous one.
+    pub fn set_fragment_shader(&self, new_shader_code: &str) -> js_sys::Promise {
+        self.shared
+            .set_project(Project::from_image(new_shader_code))
+    }
+
+    /// Resolves when the project is compiled, rejects with compile errors.
+    pub fn set_project(&self, project: JsValue) -> js_sys::Promise {
+        match parse_project(project) {
+            Ok(project) => self.shared.set_project(project),
+            Err(error) => js_sys::Promise::reject(&reject(error)),
+        }
+    }
+
+    /// Resolves when the image is decoded, rejects if it can't be used as a texture.
+    pub fn set_channel_texture(
+        &self,
+        channel: usize,
+        source: JsValue,
+        options: JsValue,
+    ) -> js_sys::Promise {
+        let shared = self.shared.clone();
+        wasm_bindgen_futures::future_to_promise(async move {
+            shared
+                .set_channel_texture(channel, source, options)
+                .await
+                .map_err(reject)?;
+            Ok(JsValue::UNDEFINED)
+        })
+    }
+
+    pub fn update_player_state(&self, state: JsValue) -> Result<(), JsValue> {
+        let state = serde_wasm_bindgen::from_value::<PlayerStateUpdate>(state)
+            .map_err(|error| reject(PlayerError::invalid_format("player state", error)))?;
+        state.validate().map_err(|error| {
+            reject(PlayerError::InvalidArgument(format!(
+                "Invalid player state: {error}"
+            )))
+        })?;
+        let custom_changed = state.custom.is_some();
+        let capture_touch = self.shared.state.borrow().capture_touch();
+        self.shared
+            .update_playback(|player_state| player_state.merge(state));
+        if self.shared.state.borrow().capture_touch() != capture_touch {
+            self.shared.update_touch_action();
+        }
+        if custom_changed {
+            self.shared.params_changed.set(true);
+            if let Some(panel) = self.shared.panel.borrow_mut().as_mut() {
+                let player_state = self.shared.state.borrow();
+                panel
+                    .show_values(player_state.custom.as_ref())
+                    .map_err(|error| {
+                        reject(PlayerError::Runtime(format!(
+                            "Failed to show shader parameters: {error:?}"
+                        )))
+                    })?;
+            }
+        }
+        self.shared.redraw_requested.set(true);
+        Ok(())
+    }
+
+    /// Custom uniforms declared by the current project, empty till it is compiled.
+    pub fn get_shader_params(&self) -> Result<JsValue, JsValue> {
+        let renderer = self.shared.try_renderer().map_err(reject)?;
+        let params = renderer
+            .pipeline
+            .as_ref()
+            .map(Pipeline::params)
+            .unwrap_or_default();
+        let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+        params.serialize(&serializer).map_err(|error| {
+            reject(PlayerError::Runtime(format!(
+                "Failed to serialize shader parameters: {error}"
+            )))
+        })
+    }
+
+    /// Snapshot of playback state, overrides and uniforms of the last rendered frame.
+    pub fn get_player_state(&self) -> Result<JsValue, JsValue> {
+        let shared = &self.shared;
+        let state = shared.state.borrow().clone();
+        let renderer = shared.try_renderer().map_err(reject)?;
+        let uniforms = renderer.last_uniforms;
+        let snapshot = PlayerStateSnapshot {
+            paused: state.paused(),
+            speed: state.speed(),
+            time: uniforms.time,
+            time_delta: uniforms.time_delta,
+            frame: uniforms.frame,
+            frame_rate: uniforms.frame_rate,
+            fps: renderer.fps as f32,
+            on_demand: renderer.is_on_demand(&state),
+            gpu: renderer.gpu_timer.as_ref().and_then(GpuTimer::stats),
+            resolution: ResolutionUniform {
+                width: uniforms.resolution[0],
+                height: uniforms.resolution[1],
+                pixel_aspect_ratio: uniforms.resolution[2],
+            },
+            render_scale: state.render_scale() * renderer.resolution.scale(),
+            state,
+        };
+        let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+        snapshot.serialize(&serializer).map_err(|error| {
+            reject(PlayerError::Runtime(format!(
+                "Failed to serialize player state: {error}"
+            )))
+        })
+    }
+
+    /// Renders the image pass offscreen and returns content of a PNG file.
+    ///
+    /// Canvas is not affected. Without options buffers of the last frame on the canvas are used.
+    pub fn screenshot(&self, options: JsValue) -> Result<Vec<u8>, JsValue> {
+        let options = serde_wasm_bindgen::from_value::<Option<CaptureOptions>>(options)
+            .map_err(|error| reject(PlayerError::invalid_format("screenshot options", error)))?
+            .unwrap_or_default();
+        self.shared.screenshot(options).map_err(reject)
+    }
+
+    /// Renders `duration * fps` frames offscreen at exact time steps and passes each one to `callback`.
+    ///
+    /// Resolves with number of rendered frames, rejects if rendering was aborted by an error.
+    pub fn render_frames(&self, options: JsValue, callback: js_sys::Function) -> js_sys::Promise {
+        let shared = self.shared.clone();
+        wasm_bindgen_futures::future_to_promise(async move {
+            let options = serde_wasm_bindgen::from_value::<RecordingOptions>(options)
+                .map_err(|error| reject(PlayerError::invalid_format("recording options", error)))?;
+            let count = shared
+                .render_frames(options, &callback)
+                .await
+                .map_err(reject)?;
+            Ok(JsValue::from(count))
+        })
+    }
+
+    pub fn play(&self) {
+        self.shared.update_playback(|state| state.set_paused(false));
+        self.shared.redraw_requested.set(true);
+    }
+
+    pub fn stop(&self) {
+        self.shared.update_playback(|state| state.set_paused(true));
+    }
+
+    /// Moves playback to `seconds`, `iFrame` is set to the matching frame at 60 fps.
+    ///
+    /// Frame is rendered even if player is paused.
+    pub fn seek(&self, seconds: f64) {
+        self.shared.seek(Seek {
+            time: seconds,
+            reset_buffers: false,
+        });
+    }
+
+    /// Pauses player and renders `n` more frames, each one advances time by 1/60 second.
+    pub fn step_frames(&self, n: u32) {
+        self.shared.update_playback(|state| state.set_paused(true));
+        let steps = &self.shared.pending_steps;
+        steps.set(steps.get().saturating_add(n));
+    }
+
+    /// Sets time and `iFrame` to zero and clears buffers.
+    pub fn restart(&self) {
+        self.shared.seek(Seek {
+            time: 0.0,
+            reset_buffers: true,
+        });
+    }
+
+    /// Shows or hides controls of playback and shader parameters next to the canvas.
+    pub fn show_controls(&self, visible: bool) -> Result<(), JsValue> {
+        PlayerShared::show_controls(&self.shared, visible).map_err(reject)
+    }
+
+    /// Stops rendering, unsubscribes from canvas events and frees GL resources.
+    pub fn destroy(&self) {
+        self.shared.destroy();
+    }
+}
+
+impl ShaderPlayer {
+    /// Fails with `PlayerError::Context` and draws a message on the canvas if it has no WebGL2.
+    pub fn from_canvas(canvas: HtmlCanvasElement) -> Result<Self, PlayerError> {
+        let gl = match gl::context::from_canvas(&canvas) {
+            Ok(gl) => gl,
+            Err(error) => {
+                draw_fallback_message(&canvas, "WebGL2 is not supported by this browser");
+                return Err(PlayerError::Context(error.to_string()));
+            }
+        };
+        let shared = Rc::new(PlayerShared {
+            canvas,
+            state: RefCell::default(),
+            project: RefCell::new(Project::from_image(DEFAULT_FRAGMENT_SHADER_SRC)),
+            // Project is compiled on the first frame
+            reload_project: Cell::new(true),
+            params_changed: Cell::new(false),
+            compile_waiters: RefCell::default(),
+            context_lost: Cell::new(false),
+            pointers: RefCell::default(),
+            seek: Cell::new(None),
+            pending_steps: Cell::new(0),
+            redraw_requested: Cell::new(false),
+            channel_textures: RefCell::default(),
+            renderer: RefCell::new(Renderer {
+                gl,
+                pipeline: None,
+                pending_pipeline: None,
+                program_cache: ProgramCache::default(),
+                last_real_time: 0.0,
+                last_playback_time: 0.0,
+                frame: 0.0,
+                pipeline_generation: 0,
+                fps: 0.0,
+                last_uniforms: FrameUniforms::default(),
+                resolution: ResolutionController::default(),
+                upscaler: None,
+                gpu_timer: None,
+                last_stats_time: 0.0,
+                events: Vec::new(),
+                params_changed: false,
+                reload_webgl2_context: false,
+            }),
+            listeners: RefCell::default(),
+            size_observer: RefCell::default(),
+            css_size: Cell::new(None),
+            visibility_observer: RefCell::default(),
+            intersecting: Cell::new(true),
+            suspended: Cell::new(false),
+            panel: RefCell::default(),
+            destroyed: Cell::new(false),
+        });
+
+        PlayerShared::subscribe(&shared);
+        PlayerShared::request_frame(Rc::downgrade(&shared));
+        Ok(Self { shared })
+    }
+}
+
+impl Drop for ShaderPlayer {
+    fn drop(&mut self) {
+        self.shared.destroy();
+    }
+}
+
+fn parse_project(project: JsValue) -> Result<Project, PlayerError> {
+    let project = serde_wasm_bindgen::from_value::<Project>(project)
+        .map_err(|error| PlayerError::invalid_format("project", error))?;
+    project
+        .validate()
+        .map_err(|error| PlayerError::InvalidArgument(format!("Invalid project: {error}")))?;
+    Ok(project)
+}
+
+/// `count` frames before `target` and `target` itself, at steps of `step_frames()`.
+///
+/// Frames before 0 are not rendered, so feedback buffers start from empty as on the canvas.
+fn warmup_frames(target: &FrameUniforms, count: u32) -> Vec<FrameUniforms> {
+    let last = target.frame.max(0);
+    let first = last
+        .saturating_sub(count.min(i32::MAX as u32) as i32)
+        .max(0);
+    let time_delta = (1.0 / STEP_FRAME_RATE) as f32;
+    (first..=last)
+        .map(|frame| FrameUniforms {
+            time: target.time - (last - frame) as f32 * time_delta,
+            time_delta: if frame == last {
+                target.time_delta
+            } else {
+                time_delta
+            },
+            frame,
+            ..*target
+        })
+        .collect()
+}
+
+/// Draws `message` in place of shader output, canvas without any context gets a 2D one.
+fn draw_fallback_message(canvas: &HtmlCanvasElement, message: &str) {
+    let Some(context) = canvas
+        .get_context("2d")
+        .ok()
+        .flatten()
+        .and_then(|context| context.dyn_into::<CanvasRenderingContext2d>().ok())
+    else {
+        gl::error!("Can not draw fallback message, canvas has a context of other type");
+        return;
+    };
+    let width = f64::from(canvas.width());
+    let height = f64::from(canvas.height());
+    context.set_fill_style_str("#202020");
+    context.fill_rect(0.0, 0.0, width, height);
+    context.set_fill_style_str("#e0e0e0");
+    context.set_font("16px sans-serif");
+    context.set_text_align("center");
+    context.set_text_baseline("middle");
+    if let Err(error) = context.fill_text(message, width / 2.0, height / 2.0) {
+        gl::error!("Can not draw fallback message {error:?}");
+    }
+}
+
+/// Event comes from a form field or editable element, typing there is not input of shaders.
+fn is_typing_target(event: &web_sys::Event) -> bool {
+    let Some(element) = event
+        .target()
+        .and_then(|target| target.dyn_into::<HtmlElement>().ok())
+    else {
+        return false;
+    };
+    element.is_content_editable()
+        || matches!(element.tag_name().as_str(), "INPUT" | "TEXTAREA" | "SELECT")
+}
+
+/// Page is in a background tab or minimized window.
+fn is_page_hidden() -> bool {
+    web_sys::window()
+        .and_then(|window| window.document())
+        .is_some_and(|document| document.hidden())
+}
+
+impl PlayerShared {
+    fn emit(&self, event: &PlayerEvent) {
+        if let Some(panel) = self.panel.borrow_mut().as_mut() {
+            match event {
+                PlayerEvent::PlaybackChanged(changed) => {
+                    panel.show_playback(changed.paused, changed.speed);
+                }
+                PlayerEvent::ShaderCompiled(compiled) => {
+                    let state = self.state.borrow();
+                    if let Err(error) =
+                        panel.set_params(compiled.params.clone(), state.custom.as_ref())
+                    {
+                        gl::error!("Can not show shader parameters {error:?}");
+                    }
+                }
+                _ => {}
+            }
+        }
+        event.dispatch(self.canvas.as_ref());
+    }
+
+    /// Creates or removes the controls overlay.
+    fn show_controls(this: &Rc<Self>, visible: bool) -> Result<(), PlayerError> {
+        if !visible {
+            this.panel.take();
+            return Ok(());
+        }
+        if this.destroyed.get() || this.panel.borrow().is_some() {
+            return Ok(());
+        }
+        let weak = Rc::downgrade(this);
+        let mut panel = ControlPanel::new(&this.canvas, move |action| {
+            if let Some(shared) = weak.upgrade() {
+                shared.apply_panel_action(action);
+            }
+        })
+        .map_err(|error| PlayerError::Runtime(format!("Failed to create controls: {error:?}")))?;
+        let params = this
+            .try_renderer()?
+            .pipeline
+            .as_ref()
+            .map(Pipeline::params)
+            .unwrap_or_default();
+        let state = this.state.borrow();
+        panel.show_playback(state.paused(), state.speed());
+        panel
+            .set_params(params, state.custom.as_ref())
+            .map_err(|error| {
+                PlayerError::Runtime(format!("Failed to show shader parameters: {error:?}"))
+            })?;
+        *this.panel.borrow_mut() = Some(panel);
+        Ok(())
+    }
+
+    fn apply_panel_action(&self, action: PanelAction) {
+        match action {
+            PanelAction::TogglePause => {
+                let paused = self.state.borrow().paused();
+                self.update_playback(|state| state.set_paused(!paused));
+            }
+            PanelAction::Speed(speed) => {
+                self.update_playback(|state| {
+                    state.merge(PlayerStateUpdate {
+                        playback: Some(Playback {
+                            speed: Some(speed),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    });
+                });
+            }
+            PanelAction::Param(name, value) => {
+                self.state.borrow_mut().merge(PlayerStateUpdate {
+                    custom: Some(Some(BTreeMap::from([(name, Some(value))]))),
+                    ..Default::default()
+                });
+                self.params_changed.set(true);
+            }
+        }
+        self.redraw_requested.set(true);
+    }
+
+    /// Applies `update` to state and emits `PlaybackChanged` if pause, speed or mode changed.
+    fn update_playback(&self, update: impl FnOnce(&mut PlayerState)) {
+        let before = PlaybackChanged::new(&self.state.borrow(), None);
+        update(&mut self.state.borrow_mut());
+        let after = PlaybackChanged::new(&self.state.borrow(), None);
+        if before != after {
+            self.emit(&PlayerEvent::PlaybackChanged(after));
+        }
+    }
+
+    fn seek(&self, seek: Seek) {
+        self.seek.set(Some(seek));
+        let changed = PlaybackChanged::new(&self.state.borrow(), Some(seek.time));
+        self.emit(&PlayerEvent::PlaybackChanged(changed));
+    }
+
+    /// Replaces the project, which is compiled in background while the previous one is drawn.
+    ///
+    /// Returned promise settles after compilation, promises of previous calls are cancelled.
+    fn set_project(&self, project: Project) -> js_sys::Promise {
+        *self.project.borrow_mut() = project;
+        self.reload_project.set(true);
+        self.redraw_requested.set(true);
+        self.settle_compile(Err(PlayerError::Cancelled(
+            "Project was replaced before it was compiled",
+        )));
+        js_sys::Promise::new(&mut |resolve, reject| {
+            self.compile_waiters
+                .borrow_mut()
+                .push(CompileWaiter { resolve, reject });
+        })
+    }
+
+    /// Settles promises of `set_project()` with result of compilation, errors are not reported here.
+    fn settle_compile(&self, result: Result<(), PlayerError>) {
+        let waiters = self.compile_waiters.take();
+        if waiters.is_empty() {
+            return;
+        }
+        let result = result.map_err(JsValue::from);
+        for waiter in waiters {
+            let settled = match &result {
+                Ok(()) => waiter.resolve.call0(&JsValue::UNDEFINED),
+                Err(error) => waiter.reject.call1(&JsValue::UNDEFINED, error),
+            };
+            if let Err(error) = settled {
+                gl::error!("Failed to settle compilation promise {error:?}");
+            }
+        }
+    }
+
+    /// Borrows renderer for an API call, fails instead of panicking if it is already borrowed.
+    fn try_renderer(&self) -> Result<Ref<'_, Renderer>, PlayerError> {
+        self.renderer
+            .try_borrow()
+            .map_err(|_| PlayerError::Busy("renderer"))
+    }
+
+    async fn set_channel_texture(
+        self: Rc<Self>,
+        channel: usize,
+        source: JsValue,
+        options: JsValue,
+    ) -> Result<(), PlayerError> {
+        if channel >= CHANNEL_COUNT {
+            return Err(PlayerError::InvalidArgument(format!(
+                "Channel {channel} is out of range, only {CHANNEL_COUNT} channels are supported"
+            )));
+        }
+        let options = serde_wasm_bindgen::from_value::<Option<TextureOptions>>(options)
+            .map_err(|error| PlayerError::invalid_format("texture options", error))?
+            .unwrap_or_default();
+
+        let bitmap = decode_image(source, options.vflip).await.map_err(|error| {
+            PlayerError::InvalidArgument(format!("Failed to decode channel texture: {error:?}"))
+        })?;
+        if self.destroyed.get() {
+            bitmap.close();
+        } else {
+            self.channel_textures
+                .borrow_mut()
+                .set(channel, ChannelTexture::new(bitmap, options));
+            self.redraw_requested.set(true);
+        }
+        Ok(())
+    }
+
+    fn screenshot(&self, options: CaptureOptions) -> Result<Vec<u8>, PlayerError> {
+        let (mut offscreen, generation) = self.create_offscreen(options.width, options.height)?;
+        let (width, height) = offscreen.size();
+        let (last, feedback_passes) = {
+            let renderer = self.try_renderer()?;
+            let feedback_passes = renderer
+                .pipeline
+                .as_ref()
+                .filter(|pipeline| pipeline.has_feedback())
+                .map(Pipeline::pass_count);
+            (renderer.last_uniforms, feedback_passes)
+        };
+        let uniforms = FrameUniforms {
+            resolution: [width as f32, height as f32, 1.0],
+            time: options.time.unwrap_or(last.time),
+            frame: match (options.frame, options.time) {
+                (Some(frame), _) => frame,
+                (None, Some(time)) => (f64::from(time) * STEP_FRAME_RATE).round() as i32,
+                (None, None) => last.frame,
+            },
+            ..last
+        };
+        let live = options.width.is_none()
+            && options.height.is_none()
+            && options.time.is_none()
+            && options.frame.is_none()
+            && options.warmup_frames.is_none();
+        let warmup;
+        let frames = match (live, feedback_passes, options.warmup_frames) {
+            (true, _, _) => CaptureFrames::Live(&uniforms),
+            (false, Some(passes), Some(count)) if count > 0 => {
+                // Larger captures and projects with more passes get fewer frames
+                let frame_fragments = u64::from(width) * u64::from(height) * passes as u64;
+                let count = (MAX_WARMUP_FRAGMENTS / frame_fragments).min(u64::from(count));
+                warmup = warmup_frames(&uniforms, count as u32);
+                CaptureFrames::Sequence(&warmup)
+            }
+            _ => CaptureFrames::Sequence(core::slice::from_ref(&uniforms)),
+        };
+        let pixels = self.capture_frame(&mut offscreen, generation, frames);
+        self.delete_offscreen(offscreen);
+        encode_png(width, height, &pixels?).map_err(PlayerError::Runtime)
+    }
+
+    async fn render_frames(
+        &self,
+        options: RecordingOptions,
+        callback: &js_sys::Function,
+    ) -> Result<u32, PlayerError> {
+        let count = options
+            .frame_count()
+            .map_err(PlayerError::InvalidArgument)?;
+        let (mut offscreen, generation) = self.create_offscreen(options.width, options.height)?;
+        let result = self
+            .render_frames_into(&mut offscreen, generation, count, options, callback)
+            .await;
+        self.delete_offscreen(offscreen);
+        result.map(|()| count)
+    }
+
+    async fn render_frames_into(
+        &self,
+        offscreen: &mut Offscreen,
+        generation: u32,
+        count: u32,
+        options: RecordingOptions,
+        callback: &js_sys::Function,
+    ) -> Result<(), PlayerError> {
+        let (width, height) = offscreen.size();
+        let last = self.try_renderer()?.last_uniforms;
+        for index in 0..count {
+            let timing = options.frame_timing(index);
+            let time = timing.time;
+            let uniforms = FrameUniforms {
+                resolution: [width as f32, height as f32, 1.0],
+                time: time as f32,
+                time_delta: timing.time_delta as f32,
+                frame: timing.frame,
+                frame_rate: options.fps as f32,
+                ..last
+            };
+            let frames = CaptureFrames::Sequence(core::slice::from_ref(&uniforms));
+            let pixels = self.capture_frame(offscreen, generation, frames)?;
+            let data = match options.format {
+                FrameFormat::Rgba => pixels,
+                FrameFormat::Png => {
+                    encode_png(width, height, &pixels).map_err(PlayerError::Runtime)?
+                }
+            };
+
+            // Borrows are released, so callback can use the player
+            let frame = js_sys::Object::new();
+            let fields = [
+                ("index", JsValue::from(index)),
+                ("time", JsValue::from(time)),
+                ("width", JsValue::from(width)),
+                ("height", JsValue::from(height)),
+                ("data", js_sys::Uint8Array::from(data.as_slice()).into()),
+            ];
+            for (key, value) in fields {
+                js_sys::Reflect::set(&frame, &JsValue::from_str(key), &value).map_err(|error| {
+                    PlayerError::Runtime(format!("Failed to build frame object: {error:?}"))
+                })?;
+            }
+            let callback_failed =
+                |error: JsValue| PlayerError::Runtime(format!("Frame callback failed: {error:?}"));
+            let reply = callback
+                .call1(&JsValue::NULL, &frame)
+                .map_err(callback_failed)?;
+            // Callback may return a promise to slow down rendering till frame is consumed
+            if let Some(promise) = reply.dyn_ref::<js_sys::Promise>() {
+                JsFuture::from(promise.clone())
+                    .await
+                    .map_err(callback_failed)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates capture target, size defaults to drawing buffer size.
+    ///
+    /// Returns generation of the pipeline, which is checked by `capture_frame()`.
+    fn create_offscreen(
+        &self,
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Result<(Offscreen, u32), PlayerError> {
+        if self.destroyed.get() || self.context_lost.get() {
+            return Err(PlayerError::Context("WebGL2 context is lost".to_owned()));
+        }
+        let renderer = self.try_renderer()?;
+        if renderer.pipeline.is_none() {
+            return Err(PlayerError::Runtime("Shader is not compiled".to_owned()));
+        }
+        let gl = &renderer.gl;
+        let width = width.unwrap_or_else(|| gl.drawing_buffer_width() as u32);
+        let height = height.unwrap_or_else(|| gl.drawing_buffer_height() as u32);
+        let offscreen = Offscreen::new(gl, width, height).map_err(PlayerError::Runtime)?;
+        Ok((offscreen, renderer.pipeline_generation))
+    }
+
+    /// Renders `frames` offscreen and reads back the last one, fails if shader was changed since `generation`.
+    fn capture_frame(
+        &self,
+        offscreen: &mut Offscreen,
+        generation: u32,
+        frames: CaptureFrames<'_>,
+    ) -> Result<Vec<u8>, PlayerError> {
+        if self.destroyed.get() || self.context_lost.get() {
+            return Err(PlayerError::Context("WebGL2 context is lost".to_owned()));
+        }
+        let renderer = self.try_renderer()?;
+        let pipeline = match &renderer.pipeline {
+            Some(pipeline) if renderer.pipeline_generation == generation => pipeline,
+            _ => {
+                return Err(PlayerError::Runtime(
+                    "Shader was changed during capture".to_owned(),
+                ))
+            }
+        };
+        let gl = &renderer.gl;
+        let mut textures = self.channel_textures.borrow_mut();
+        textures.prepare(gl);
+        match frames {
+            CaptureFrames::Live(uniforms) => {
+                if !offscreen.draw_image(gl, pipeline, uniforms, &textures) {
+                    offscreen.draw(gl, pipeline, uniforms, &textures);
+                }
+            }
+            CaptureFrames::Sequence(frames) => {
+                for uniforms in frames {
+                    offscreen.draw(gl, pipeline, uniforms, &textures);
+                }
+            }
+        }
+        offscreen.read_pixels(gl).map_err(PlayerError::Runtime)
+    }
+
+    fn delete_offscreen(&self, offscreen: Offscreen) {
+        // Objects of a lost context are already gone
+        if !self.destroyed.get() && !self.context_lost.get() {
+            offscreen.delete(&self.renderer.borrow().gl);
+        }
+    }
+
+    fn add_listener(
+        this: &Rc<Self>,
+        target: &EventTarget,
+        event_type: &'static str,
+        handler: impl Fn(&PlayerShared, web_sys::Event) + 'static,
+    ) {
+        let weak = Rc::downgrade(this);
+        let closure = Closure::<dyn FnMut(web_sys::Event)>::new(move |event| {
+            if let Some(shared) = weak.upgrade() {
+                handler(&shared, event);
+            }
+        });
+        let callback = closure.as_ref().unchecked_ref::<js_sys::Function>();
+        if let Err(error) = target.add_event_listener_with_callback(event_type, callback) {
+            gl::error!("Can not subscribe to {event_type} events {error:?}");
+        }
+        this.listeners.borrow_mut().push(Listener {
+            target: target.clone(),
+            event_type,
+            closure,
+        });
+    }
+
+    fn subscribe(this: &Rc<Self>) {
+        let canvas: &EventTarget = this.canvas.as_ref();
+        Self::add_listener(this, canvas, "webglcontextlost", |shared, event| {
+            gl::error!("Canvas lost WebGL2 context");
+            event.prevent_default();
+            shared.context_lost.set(true);
+            shared.emit(&PlayerEvent::ContextLost);
+        });
+
+        Self::add_listener(this, canvas, "webglcontextrestored", |shared, _| {
+            gl::info!("Canvas restored WebGL2 context");
+            shared.context_lost.set(false);
+            shared.emit(&PlayerEvent::ContextRestored);
+        });
+
+        Self::add_listener(this, canvas, "pointerdown", |shared, event| {
+            let event: &PointerEvent = event.unchecked_ref();
+            // Drags are started by the main button as mouse down did, touches and pens report it too
+            if event.button() != 0 {
+                return;
+            }
+            // Moves and release are delivered to the canvas even outside of it
+            if let Err(error) = shared.canvas.set_pointer_capture(event.pointer_id()) {
+                gl::error!("Can not capture pointer {error:?}");
+            }
+            let (x, y) = shared.mouse_position(event);
+            shared
+                .pointers
+                .borrow_mut()
+                .down(event.pointer_id(), event.is_primary(), (x, y));
+            shared.redraw_requested.set(true);
+            if event.is_primary() {
+                shared.state.borrow_mut().update_mouse(|_| {
+                    Some(MouseUniform {
+                        x,
+                        y,
+                        down_x: x,
+                        down_y: y,
+                    })
+                });
+            }
+        });
+
+        Self::add_listener(this, canvas, "pointermove", |shared, event| {
+            let event: &PointerEvent = event.unchecked_ref();
+            let (x, y) = shared.mouse_position(event);
+            let mut pointers = shared.pointers.borrow_mut();
+            let is_primary = pointers.moved(event.pointer_id(), (x, y));
+            // Hover doesn't change uniforms
+            if pointers.any_pressed() {
+                shared.redraw_requested.set(true);
+            }
+            if is_primary {
+                shared.state.borrow_mut().update_mouse(|old_uniform| {
+                    Some(if let Some(old_uniform) = old_uniform {
+                        MouseUniform {
+                            x,
+                            y,
+                            ..old_uniform
+                        }
+                    } else {
+                        MouseUniform {
+                            x,
+                            y,
+                            down_x: x,
+                            down_y: y,
+                        }
+                    })
+                });
+            }
+        });
+
+        // Cancel comes when browser takes over the touch, leave when pointer capture failed
+        for event_type in [
+            "pointerup",
+            "pointercancel",
+            "pointerleave",
+            "lostpointercapture",
+        ] {
+            Self::add_listener(this, canvas, event_type, |shared, event| {
+                let event: &PointerEvent = event.unchecked_ref();
+                if shared.pointers.borrow_mut().up(event.pointer_id()) {
+                    shared.release_mouse();
+                }
+                shared.redraw_requested.set(true);
+            });
+        }
+
+        this.pin_intrinsic_size();
+        this.update_touch_action();
+        Self::observe_size(this);
+        Self::observe_visibility(this);
+
+        // Keys are tracked for the whole page, as canvas doesn't get focus by default
+        let Some(window) = web_sys::window() else {
+            gl::error!("Failed to get window for keyboard events");
+            return;
+        };
+        // Animation frames stop in hidden tabs, so suspension is noticed here
+        if let Some(document) = window.document() {
+            Self::add_listener(this, document.as_ref(), "visibilitychange", |shared, _| {
+                if is_page_hidden() {
+                    shared.suspended.set(true);
+                }
+            });
+        }
+        let window: &EventTarget = window.as_ref();
+        Self::add_listener(this, window, "keydown", |shared, event| {
+            // Keys released in fields are still handled, so keys pressed before focusing them don't stick
+            if is_typing_target(&event) {
+                return;
+            }
+            let event: &KeyboardEvent = event.unchecked_ref();
+            let mut textures = shared.channel_textures.borrow_mut();
+            textures.keyboard.key_down(event.key_code());
+            shared.redraw_requested.set(true);
+        });
+
+        Self::add_listener(this, window, "keyup", |shared, event| {
+            let event: &KeyboardEvent = event.unchecked_ref();
+            let mut textures = shared.channel_textures.borrow_mut();
+            textures.keyboard.key_up(event.key_code());
+            shared.redraw_requested.set(true);
+        });
+
+        // Keyup and pointerup are not delivered when page loses focus with a key or pointer held
+        Self::add_listener(this, window, "blur", |shared, _| {
+            shared.channel_textures.borrow_mut().keyboard.release_all();
+            if shared.pointers.borrow_mut().release_all() {
+                shared.release_mouse();
+            }
+            shared.redraw_requested.set(true);
+        });
+    }
+
+    fn observe_size(this: &Rc<Self>) {
+        let weak = Rc::downgrade(this);
+        let closure = Closure::<dyn FnMut(js_sys::Array)>::new(move |entries: js_sys::Array| {
+            let Some(shared) = weak.upgrade() else {
+                return;
+            };
+            // Only the canvas is observed, so the last entry is its latest size
+            if let Some(entry) = entries.pop().dyn_ref::<ResizeObserverEntry>() {
+                let rect = entry.content_rect();
+                shared.resized((rect.width(), rect.height()));
+                if let Some(panel) = shared.panel.borrow().as_ref() {
+                    panel.place(&shared.canvas);
+                }
+            }
+        });
+        let observer = match ResizeObserver::new(closure.as_ref().unchecked_ref()) {
+            Ok(observer) => observer,
+            Err(error) => {
+                gl::error!("Can not observe canvas size {error:?}");
+                return;
+            }
+        };
+        observer.observe(&this.canvas);
+        *this.size_observer.borrow_mut() = Some(SizeObserver {
+            observer,
+            _closure: closure,
+        });
+    }
+
+    fn observe_visibility(this: &Rc<Self>) {
+        let weak = Rc::downgrade(this);
+        let closure = Closure::<dyn FnMut(js_sys::Array)>::new(move |entries: js_sys::Array| {
+            let Some(shared) = weak.upgrade() else {
+                return;
+            };
+            if let Some(entry) = entries.pop().dyn_ref::<IntersectionObserverEntry>() {
+                shared.intersecting.set(entry.is_intersecting());
+            }
+        });
+        let observer = match IntersectionObserver::new(closure.as_ref().unchecked_ref()) {
+            Ok(observer) => observer,
+            Err(error) => {
+                gl::error!("Can not observe canvas visibility {error:?}");
+                return;
+            }
+        };
+        observer.observe(&this.canvas);
+        *this.visibility_observer.borrow_mut() = Some(VisibilityObserver {
+            observer,
+            _closure: closure,
+        });
+    }
+
+    /// Takes CSS size reported by the observer.
+    fn resized(&self, size: (f64, f64)) {
+        self.css_size.set(Some(size));
+    }
+
+    /// Pins dimensions of the canvas, which have no size from the page, to their current size.
+    ///
+    /// Such dimensions are laid out at the size of the drawing buffer, so setting the buffer from
+    /// the observed size would resize the canvas again on every report. They can't be told apart
+    /// by computed style, which is always in pixels, so they are found once before observing: the
+    /// buffer is made one pixel larger and dimensions which follow it get inline `width` or
+    /// `height`. Sizes given by the page are never touched, and a hidden canvas is left as is.
+    fn pin_intrinsic_size(&self) {
+        let element: &Element = self.canvas.as_ref();
+        let Some(computed) =
+            web_sys::window().and_then(|window| window.get_computed_style(element).ok().flatten())
+        else {
+            return;
+        };
+        // Values before the probe, as layout is computed again when they are read after it
+        let pinned = [
+            ("width", computed.get_property_value("width")),
+            ("height", computed.get_property_value("height")),
+        ];
+        let (width, height) = (self.canvas.width(), self.canvas.height());
+        let before = (element.client_width(), element.client_height());
+        self.canvas.set_width(width + 1);
+        self.canvas.set_height(height + 1);
+        let after = (element.client_width(), element.client_height());
+        self.canvas.set_width(width);
+        self.canvas.set_height(height);
+
+        let style = self.canvas.style();
+        let follows = [before.0 != after.0, before.1 != after.1];
+        for ((property, value), follows) in pinned.into_iter().zip(follows) {
+            let result = match value {
+                Ok(value) if follows => style.set_property(property, &value),
+                Ok(_) => Ok(()),
+                Err(error) => Err(error),
+            };
+            if let Err(error) = result {
+                gl::error!("Can not pin CSS {property} of canvas {error:?}");
+            }
+        }
+    }
+
+    /// Sets drawing buffer size from CSS size, `devicePixelRatio` and render scale.
+    ///
+    /// Returns `true` if size was changed, canvas is cleared in this case.
+    fn update_canvas_size(&self) -> bool {
+        let Some((css_width, css_height)) = self.css_size.get() else {
+            return false;
+        };
+        let pixel_ratio = web_sys::window().map_or(1.0, |window| window.device_pixel_ratio());
+        let scale = pixel_ratio * f64::from(self.state.borrow().render_scale());
+        // Browser scales drawing buffer to CSS size of the canvas
+        let width = (css_width * scale).round().max(1.0) as u32;
+        let height = (css_height * scale).round().max(1.0) as u32;
+        if self.canvas.width() == width && self.canvas.height() == height {
+            return false;
+        }
+        self.canvas.set_width(width);
+        self.canvas.set_height(height);
+        true
+    }
+
+    /// Browser scrolls or zooms the page instead of sending touch moves and cancels the touch,
+    /// unless `touch-action` is `none`. It is set by default, `input.capture_touch: false` removes it.
+    fn update_touch_action(&self) {
+        let style = self.canvas.style();
+        let result = if self.state.borrow().capture_touch() {
+            style.set_property("touch-action", "none")
+        } else {
+            style.remove_property("touch-action").map(drop)
+        };
+        if let Err(error) = result {
+            gl::error!("Can not change touch actions of canvas {error:?}");
+        }
+    }
+
+    /// Negates `iMouse.z` on release of the primary pointer, as Shadertoy does.
+    fn release_mouse(&self) {
+        let mut state = self.state.borrow_mut();
+        if state.mouse_mode() == MouseMode::Shadertoy {
+            state.update_mouse(|mouse| {
+                mouse.map(|mouse| MouseUniform {
+                    down_x: -mouse.down_x.abs(),
+                    ..mouse
+                })
+            });
+        }
+    }
+
+    /// Position of pointer in coordinates of `iMouse`, see `MouseMode`.
+    fn mouse_position(&self, mouse_event: &MouseEvent) -> (f32, f32) {
+        let rect = self
+            .canvas
+            .unchecked_ref::<Element>()
+            .get_bounding_client_rect();
+        let x = mouse_event.client_x() as f32 - rect.left() as f32;
+        let y = mouse_event.client_y() as f32 - rect.top() as f32;
+        if self.state.borrow().mouse_mode() == MouseMode::Legacy
+            || rect.width() <= 0.0
+            || rect.height() <= 0.0
+        {
+            return (x, y);
+        }
+        // Same space as `fragCoord`, rounded down to pixel as on Shadertoy. Edges of the canvas
+        // and drags outside of it map to the nearest pixel, so `y == 0` is row `height - 1`
+        let canvas_size = (self.canvas.width() as i32, self.canvas.height() as i32);
+        let (width, height) = self.renderer.borrow().resolution.render_size(canvas_size);
+        let (width, height) = (width as f32, height as f32);
+        let x = (x / rect.width() as f32 * width).floor();
+        let y = (height - y / rect.height() as f32 * height).floor();
+        (
+            x.clamp(0.0, (width - 1.0).max(0.0)),
+            y.clamp(0.0, (height - 1.0).max(0.0)),
+        )
+    }
+
+    /// Schedules the next frame, the loop ends when player is destroyed or dropped.
+    fn request_frame(weak: Weak<Self>) {
+        let callback = Closure::once_into_js(move |t: f64| {
+            let Some(shared) = weak.upgrade() else {
+                return;
+            };
+            if shared.destroyed.get() {
+                return;
+            }
+            shared.render_frame(t);
+            Self::request_frame(weak);
+        });
+        let Some(window) = web_sys::window() else {
+            gl::error!("Failed to get window for render loop");
+            return;
+        };
+        if let Err(error) = window.request_animation_frame(callback.unchecked_ref()) {
+            gl::error!("Failed to request animation frame {error:?}");
+        }
+    }
+
+    fn render_frame(&self, t: f64) {
+        // Nothing is drawn while canvas can't be seen, and the first frame after that only
+        // resyncs time, so `iTimeDelta` doesn't include time spent hidden
+        let draw = if !self.intersecting.get() || is_page_hidden() {
+            self.suspended.set(true);
+            false
+        } else {
+            !self.suspended.replace(false)
+        };
+
+        // Errors are reported after all borrows are released, so listeners can call the player back
+        let (errors, events) = {
+            let mut renderer = self.renderer.borrow_mut();
+            let mut errors = Vec::new();
+            let ready = renderer.update_pipeline(self, &mut errors);
+            if !draw {
+                renderer.skip_frame(t / 1000.0);
+            } else if ready {
+                errors.extend(renderer.update_and_draw(self, t));
+            }
+            (errors, core::mem::take(&mut renderer.events))
+        };
+        for error in errors {
+            report_error(&error);
+            if let PlayerError::Compile(_) = error {
+                self.settle_compile(Err(error));
+            }
+        }
+        for event in &events {
+            if let PlayerEvent::ShaderCompiled(_) = event {
+                self.settle_compile(Ok(()));
+            }
+            self.emit(event);
+        }
+    }
+
+    fn destroy(&self) {
+        if self.destroyed.replace(true) {
+            return;
+        }
+        self.panel.take();
+
+        for listener in self.listeners.take() {
+            let callback = listener
+                .closure
+                .as_ref()
+                .unchecked_ref::<js_sys::Function>();
+            if let Err(error) = listener
+                .target
+                .remove_event_listener_with_callback(listener.event_type, callback)
+            {
+                gl::error!(
+                    "Can not unsubscribe from {} events {:?}",
+                    listener.event_type,
+                    error
+                );
+            }
+        }
+
+        if let Some(size_observer) = self.size_observer.take() {
+            size_observer.observer.disconnect();
+        }
+        if let Some(visibility_observer) = self.visibility_observer.take() {
+            visibility_observer.observer.disconnect();
+        }
+        self.settle_compile(Err(PlayerError::Cancelled("Player was destroyed")));
+
+        let Ok(mut renderer) = self.renderer.try_borrow_mut() else {
+            gl::error!("Player is destroyed during rendering, GL resources are freed with context");
+            return;
+        };
+        let renderer = &mut *renderer;
+        if let Some(pipeline) = renderer.pipeline.take() {
+            pipeline.delete(&renderer.gl);
+        }
+        if let Some((pending_pipeline, _)) = renderer.pending_pipeline.take() {
+            pending_pipeline.delete(&renderer.gl, &mut renderer.program_cache);
+        }
+        renderer.program_cache.delete(&renderer.gl);
+        if let Some(upscaler) = renderer.upscaler.take() {
+            upscaler.delete(&renderer.gl);
+        }
+        if let Some(gpu_timer) = renderer.gpu_timer.take() {
+            gpu_timer.delete(&renderer.gl);
+        }
+        // Context is not lost on purpose: canvas keeps it, and a new player of the canvas reuses it.
+        // Without resources it is cheap, and it is collected together with the canvas
+        self.channel_textures.borrow_mut().delete(&renderer.gl);
+    }
+}
+
+impl Renderer {
+    /// Skips a frame at `t` seconds, so time of skipped frames doesn't get into `iTimeDelta`.
+    fn skip_frame(&mut self, t: f64) {
+        self.last_real_time = t;
+        self.fps = 0.0;
+    }
+
+    /// Frames are rendered only when input changes, see `PlaybackMode`.
+    fn is_on_demand(&self, state: &PlayerState) -> bool {
+        match state.playback_mode() {
+            PlaybackMode::Auto => self.pipeline.as_ref().is_some_and(Pipeline::is_static),
+            PlaybackMode::Continuous => false,
+            PlaybackMode::OnDemand => true,
+        }
+    }
+
+    /// Handles context loss, starts and polls compilation and uploads custom uniforms.
+    ///
+    /// Runs on every tick, even while canvas is hidden, so promises of `set_project()` settle
+    /// and captures work for canvases which were never shown. Returns `false` while context is lost.
+    fn update_pipeline(&mut self, shared: &PlayerShared, errors: &mut Vec<PlayerError>) -> bool {
+        let gl = &self.gl;
+        let mut force_reload_shader = false;
+        match (shared.context_lost.get(), self.reload_webgl2_context) {
+            (true, false) => {
+                // Free resources
+                if let Some(pipeline) = self.pipeline.take() {
+                    pipeline.delete(gl);
+                }
+                // Compilation restarts with the new context, and programs of the lost one can't be reused
+                if let Some((pending_pipeline, _)) = self.pending_pipeline.take() {
+                    pending_pipeline.delete(gl, &mut self.program_cache);
+                }
+                self.program_cache.clear();
+                if let Some(upscaler) = self.upscaler.take() {
+                    upscaler.delete(gl);
+                }
+                if let Some(gpu_timer) = self.gpu_timer.take() {
+                    gpu_timer.delete(gl);
+                }
+                self.pipeline_generation = self.pipeline_generation.wrapping_add(1);
+                shared.channel_textures.borrow_mut().invalidate();
+                self.reload_webgl2_context = true;
+                return false;
+            }
+            (true, true) => {
+                return false;
+            }
+            (false, true) => {
+                gl::info!("forsing shader reload");
+                force_reload_shader = true;
+                self.reload_webgl2_context = false;
+                shared.redraw_requested.set(true);
+            }
+            _ => {}
+        }
+
+        // Previous pipeline is drawn till the new one is compiled, so the page doesn't freeze
+        let mut compiled = None;
+        let reload_project = shared.reload_project.take();
+        if force_reload_shader || reload_project {
+            if let Some((pending_pipeline, _)) = self.pending_pipeline.take() {
+                pending_pipeline.delete(gl, &mut self.program_cache);
+            }
+            let started = Date::now();
+            let pending_pipeline = with_library(|library| {
+                Pipeline::start_compile(
+                    gl,
+                    VERTEX_SHADER_SRC,
+                    &shared.project.borrow(),
+                    library,
+                    &mut self.program_cache,
+                )
+            });
+            match pending_pipeline {
+                Ok(pending_pipeline) => self.pending_pipeline = Some((pending_pipeline, started)),
+                Err(error) => compiled = Some(Err(error)),
+            }
+        }
+        if let Some((pending_pipeline, started)) = self
+            .pending_pipeline
+            .take_if(|(pending_pipeline, _)| pending_pipeline.is_ready(gl))
+        {
+            compiled = Some(
+                pending_pipeline
+                    .finish(gl, &mut self.program_cache)
+                    .map(|pipeline| (pipeline, started)),
+            );
+        }
+        if let Some(compiled) = compiled {
+            match compiled {
+                Ok((new_pipeline, started)) => {
+                    self.events
+                        .push(PlayerEvent::ShaderCompiled(ShaderCompiled {
+                            compile_time_ms: Date::now() - started,
+                            passes: new_pipeline.active_uniforms(gl),
+                            params: new_pipeline.params(),
+                        }));
+                    if let Some(old_pipeline) = self.pipeline.replace(new_pipeline) {
+                        old_pipeline.into_cache(gl, &mut self.program_cache);
+                    }
+                    self.pipeline_generation = self.pipeline_generation.wrapping_add(1);
+                    shared.params_changed.set(true);
+                    shared.redraw_requested.set(true);
+                    gl::info!("shader reloaded");
+                }
+                Err(error) => {
+                    self.events
+                        .push(PlayerEvent::ShaderCompileFailed(ShaderCompileFailed {
+                            errors: error.details(),
+                        }));
+                    errors.push(PlayerError::Compile(error));
+                }
+            }
+        }
+
+        if shared.params_changed.take() {
+            if let Some(pipeline) = &self.pipeline {
+                pipeline.apply_params(gl, shared.state.borrow().custom.as_ref());
+            }
+            self.params_changed = true;
+        }
+        true
+    }
+
+    /// Renders a frame at `t` milliseconds, returns errors to report.
+    fn update_and_draw(&mut self, shared: &PlayerShared, mut t: f64) -> Vec<PlayerError> {
+        let mut errors = Vec::new();
+        let gl = &self.gl;
+        t /= 1000f64;
+
+        let seek = shared.seek.take();
+        if let Some(seek) = seek {
+            self.last_playback_time = seek.time;
+            self.frame = (seek.time * STEP_FRAME_RATE).round() as f32;
+            if seek.reset_buffers {
+                if let Some(pipeline) = &mut self.pipeline {
+                    pipeline.reset_buffers(gl);
+                }
+            }
+            if self.last_real_time == 0.0 {
+                // Keep seeked time on the first frame
+                self.last_real_time = t;
+            }
+        }
+
+        let resized = shared.update_canvas_size();
+
+        // Disable render if paused, except for requested steps and frames after seek or resize.
+        // On-demand playback also skips frames, till input changes
+        let player_state = shared.state.borrow().clone();
+        let paused = player_state.paused();
+        let params_changed = core::mem::take(&mut self.params_changed);
+        // Resize clears the canvas, so the frame is drawn again, as well as change of parameters
+        let redraw = seek.is_some() || resized || params_changed;
+        let input_changed = shared.redraw_requested.take();
+        let idle = self.is_on_demand(&player_state) && !input_changed;
+        // Seeked frame is shown before the next step
+        let step = paused && !redraw && shared.pending_steps.get() > 0;
+        if step {
+            shared.pending_steps.set(shared.pending_steps.get() - 1);
+        } else if (paused || idle) && !redraw {
+            // Do nothing, except update last_real_time to prevent accumulation of time_delta
+            self.skip_frame(t);
+            return errors;
+        }
+
+        // Image is rendered in reduced size and stretched to the canvas, if adaptive resolution is on
+        let adaptive = AdaptiveOptions::from_render(player_state.render);
+        if adaptive.is_none() {
+            self.resolution = ResolutionController::default();
+        }
+        let canvas_size = (gl.drawing_buffer_width(), gl.drawing_buffer_height());
+        let mut render_size = self.resolution.render_size(canvas_size);
+        if render_size != canvas_size && self.upscaler.is_none() {
+            match Upscaler::new(gl, VERTEX_SHADER_SRC) {
+                Ok(upscaler) => self.upscaler = Some(upscaler),
+                Err(error) => {
+                    errors.push(PlayerError::Runtime(format!(
+                        "Failed to create upscaler for adaptive resolution: {error}"
+                    )));
+                    self.resolution = ResolutionController::default();
+                    render_size = canvas_size;
+                }
+            }
+        }
+
+        // u_resolution
+        let resolution = if let Some(Uniforms {
+            resolution: Some(resolution),
+            ..
+        }) = player_state.uniforms
+        {
+            [
+                resolution.width,
+                resolution.height,
+                resolution.pixel_aspect_ratio,
+            ]
+        } else {
+            [
+                render_size.0 as f32,
+                render_size.1 as f32,
+                if let Some(window) = web_sys::window() {
+                    window.device_pixel_ratio() as f32
+                } else {
+                    1.0
+                },
+            ]
+        };
+
+        // This code is designed to seamlessly continue playback after `Resume`
+        let (playback_time, playback_time_delta) = if paused {
+            // Steps advance by fixed interval, frame after seek shows seeked time
+            let playback_time_delta = if step { 1.0 / STEP_FRAME_RATE } else { 0.0 };
+            self.last_playback_time += playback_time_delta;
+            (self.last_playback_time, playback_time_delta)
+        } else if self.last_real_time == 0.0 {
+            // First frame, just init
+            self.last_playback_time = t;
+            (self.last_playback_time, 0.0)
+        } else {
+            let real_time_delta = t - self.last_real_time;
+            if real_time_delta > 0.0 {
+                let fps = 1.0 / real_time_delta;
+                self.fps = if self.fps == 0.0 {
+                    fps
+                } else {
+                    self.fps * (1.0 - FPS_SMOOTHING) + fps * FPS_SMOOTHING
+                };
+                if let Some(adaptive) = adaptive {
+                    self.resolution.update(real_time_delta, adaptive);
+                }
+            }
+            let playback_time_delta = real_time_delta
+                * f64::from(
+                    if let Some(Playback {
+                        speed: Some(speed), ..
+                    }) = player_state.playback
+                    {
+                        speed
+                    } else {
+                        1.0
+                    },
+                );
+            self.last_playback_time += playback_time_delta;
+            (self.last_playback_time, playback_time_delta)
+        };
+
+        // u_time
+        let time = if let Some(Uniforms {
+            time: Some(fixed_time),
+            ..
+        }) = player_state.uniforms
+        {
+            fixed_time
+        } else {
+            playback_time as f32
+        };
+
+        // u_time_delta
+        let time_delta = if let Some(Uniforms {
+            time_delta: Some(fixed_time_delta),
+            ..
+        }) = player_state.uniforms
+        {
+            fixed_time_delta
+        } else {
+            playback_time_delta as f32
+        };
+        self.last_real_time = t;
+
+        // u_frame
+        let current_frame = if let Some(Uniforms {
+            frame: Some(fixed_frame),
+            ..
+        }) = player_state.uniforms
+        {
+            fixed_frame
+        } else {
+            self.frame
+        } as i32;
+        self.frame += 1f32;
+
+        // u_frame_rate
+        let frame_rate = if let Some(Uniforms {
+            frame_rate: Some(fixed_frame_rate),
+            ..
+        }) = player_state.uniforms
+        {
+            fixed_frame_rate
+        } else {
+            1f32 / time_delta
+        };
+
+        // u_mouse
+        let mouse = if let Some(Uniforms {
+            mouse:
+                Some(MouseUniform {
+                    x,
+                    y,
+                    down_x,
+                    down_y,
+                }),
+            ..
+        }) = player_state.uniforms
+        {
+            Some([x, y, down_x, down_y])
+        } else {
+            None
+        };
+
+        // u_date
+        let date = if let Some(Uniforms {
+            date: Some(replaced_date),
+            ..
+        }) = player_state.uniforms
+        {
+            [
+                replaced_date.year,
+                replaced_date.month,
+                replaced_date.day,
+                replaced_date.time,
+            ]
+        } else {
+            let date = Date::new_0();
+            [
+                date.get_full_year() as f32,
+                date.get_month() as f32,
+                date.get_day() as f32,
+                (date.get_hours() * 3600 + date.get_minutes() * 60 + date.get_seconds()) as f32,
+            ]
+        };
+
+        let frame_uniforms = FrameUniforms {
+            resolution,
+            time,
+            time_delta,
+            frame: current_frame,
+            frame_rate,
+            mouse,
+            date,
+            touches: shared.pointers.borrow().uniform(),
+        };
+
+        // Draw buffers and image
+        let mut textures = shared.channel_textures.borrow_mut();
+        textures.prepare(gl);
+        if player_state.gpu_timing() {
+            let gpu_timer = self.gpu_timer.get_or_insert_with(|| GpuTimer::new(gl));
+            gpu_timer.poll(gl);
+            gpu_timer.begin(gl);
+        } else if let Some(gpu_timer) = self.gpu_timer.take() {
+            gpu_timer.delete(gl);
+        }
+        if let Some(pipeline) = &mut self.pipeline {
+            let scaled = match &mut self.upscaler {
+                Some(upscaler) if render_size != canvas_size => upscaler.target(gl, render_size),
+                _ => None,
+            };
+            match (&scaled, &self.upscaler) {
+                (Some(framebuffer), Some(upscaler)) => {
+                    pipeline.draw(
+                        gl,
+                        &frame_uniforms,
+                        &textures,
+                        Some(framebuffer),
+                        render_size,
+                    );
+                    upscaler.present(gl, canvas_size);
+                }
+                _ => pipeline.draw(gl, &frame_uniforms, &textures, None, canvas_size),
+            }
+        }
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.end(gl);
+            if t - self.last_stats_time >= STATS_EVENT_INTERVAL {
+                self.last_stats_time = t;
+                self.events.push(PlayerEvent::FrameStats(FrameStats {
+                    fps: self.fps,
+                    gpu: gpu_timer.stats(),
+                }));
+            }
+        }
+        if textures.keyboard.end_frame() {
+            shared.redraw_requested.set(true);
+        }
+        // `iMouse.w` is positive only on the first frame after click
+        if shared.pointers.borrow_mut().take_click() {
+            shared.redraw_requested.set(true);
+            let mut state = shared.state.borrow_mut();
+            if state.mouse_mode() == MouseMode::Shadertoy {
+                state.update_mouse(|mouse| {
+                    mouse.map(|mouse| MouseUniform {
+                        down_y: -mouse.down_y.abs(),
+                        ..mouse
+                    })
+                });
+            }
+        }
+        self.events.push(PlayerEvent::FrameRendered(FrameRendered {
+            frame: frame_uniforms.frame,
+            time: frame_uniforms.time,
+            time_delta: frame_uniforms.time_delta,
+        }));
+        self.last_uniforms = frame_uniforms;
+        errors
+    }
+}
@@ -0,0 +1,102 @@
+//! Pressed pointers (mouse buttons, touches and pens) for `iMouse` and `iTouch`.
+
+/// Length of `iTouch` array, pointers pressed after it is full are ignored.
+pub const TOUCH_COUNT: usize = 4;
+
+/// Pressed pointer in canvas coordinates.
+#[derive(Clone, Copy, Debug)]
+struct Touch {
+    pointer_id: i32,
+    x: f32,
+    y: f32,
+    down_x: f32,
+    down_y: f32,
+}
+
+/// Pointers held over the canvas, slots keep their index while pointer is pressed.
+#[derive(Default)]
+pub struct Pointers {
+    touches: [Option<Touch>; TOUCH_COUNT],
+    /// Pointer which drives `iMouse`
+    primary: Option<i32>,
+    /// Primary pointer was pressed after the last rendered frame
+    clicked: bool,
+}
+
+impl Pointers {
+    /// Starts tracking a pointer, primary one drives `iMouse` till release.
+    pub fn down(&mut self, pointer_id: i32, is_primary: bool, (x, y): (f32, f32)) {
+        self.up(pointer_id);
+        if let Some(slot) = self.touches.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(Touch {
+                pointer_id,
+                x,
+                y,
+                down_x: x,
+                down_y: y,
+            });
+        }
+        if is_primary {
+            self.primary = Some(pointer_id);
+            self.clicked = true;
+        }
+    }
+
+    /// Updates position of a tracked pointer, returns `true` if it drives `iMouse`.
+    pub fn moved(&mut self, pointer_id: i32, (x, y): (f32, f32)) -> bool {
+        if let Some(touch) = self.find(pointer_id) {
+            touch.x = x;
+            touch.y = y;
+        }
+        self.primary == Some(pointer_id)
+    }
+
+    pub fn any_pressed(&self) -> bool {
+        self.touches.iter().any(Option::is_some) || self.primary.is_some()
+    }
+
+    /// Stops tracking a released or cancelled pointer, returns `true` if it drove `iMouse`.
+    pub fn up(&mut self, pointer_id: i32) -> bool {
+        for slot in &mut self.touches {
+            if slot.is_some_and(|touch| touch.pointer_id == pointer_id) {
+                *slot = None;
+            }
+        }
+        let is_primary = self.primary == Some(pointer_id);
+        if is_primary {
+            self.primary = None;
+        }
+        is_primary
+    }
+
+    /// Releases all pointers, returns `true` if one of them drove `iMouse`.
+    ///
+    /// Used when page loses focus and release events are not delivered.
+    pub fn release_all(&mut self) -> bool {
+        let had_primary = self.primary.is_some();
+        self.touches = [None; TOUCH_COUNT];
+        self.primary = None;
+        had_primary
+    }
+
+    /// Returns `true` once per click, called after a frame is rendered.
+    pub fn take_click(&mut self) -> bool {
+        core::mem::take(&mut self.clicked)
+    }
+
+    /// Value of `iTouch`: xy is current position, zw is press position, zero for free slots.
+    pub fn uniform(&self) -> [[f32; 4]; TOUCH_COUNT] {
+        self.touches.map(|slot| {
+            slot.map_or([0.0; 4], |touch| {
+                [touch.x, touch.y, touch.down_x, touch.down_y]
+            })
+        })
+    }
+
+    fn find(&mut self, pointer_id: i32) -> Option<&mut Touch> {
+        self.touches
+            .iter_mut()
+            .flatten()
+            .find(|touch| touch.pointer_id == pointer_id)
+    }
+}
@@ -0,0 +1,171 @@
+//! Compilation of GL programs, which keeps info logs for diagnostics.
+
+use core::fmt;
+use web_sys::{WebGl2RenderingContext as GL, WebGlProgram, WebGlShader};
+
+/// Step of program creation, which failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgramStage {
+    VertexShader,
+    FragmentShader,
+    Link,
+}
+
+/// Failed program creation with info log of the driver.
+#[derive(Clone, Debug)]
+pub struct ProgramError {
+    pub stage: ProgramStage,
+    pub log: String,
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.stage {
+            ProgramStage::VertexShader => {
+                write!(f, "Vertex shader compilation error: {}", self.log)
+            }
+            ProgramStage::FragmentShader => {
+                write!(f, "Fragment shader compilation error: {}", self.log)
+            }
+            ProgramStage::Link => write!(f, "Program linking error: {}", self.log),
+        }
+    }
+}
+
+/// `COMPLETION_STATUS_KHR` of `KHR_parallel_shader_compile`
+const COMPLETION_STATUS_KHR: u32 = 0x91B1;
+
+fn start_shader(
+    gl: &GL,
+    shader_type: u32,
+    source: &str,
+    stage: ProgramStage,
+) -> Result<WebGlShader, ProgramError> {
+    let shader = gl.create_shader(shader_type).ok_or_else(|| ProgramError {
+        stage,
+        log: "Failed to create shader".to_owned(),
+    })?;
+    gl.shader_source(&shader, source);
+    gl.compile_shader(&shader);
+    Ok(shader)
+}
+
+/// Info log of `shader` if it failed to compile.
+fn shader_error(gl: &GL, shader: &WebGlShader, stage: ProgramStage) -> Option<ProgramError> {
+    let compiled = gl
+        .get_shader_parameter(shader, GL::COMPILE_STATUS)
+        .as_bool()
+        .unwrap_or(false);
+    (!compiled).then(|| ProgramError {
+        stage,
+        log: gl.get_shader_info_log(shader).unwrap_or_default(),
+    })
+}
+
+/// Program, which is compiled and linked by the driver, possibly on a background thread.
+pub struct PendingProgram {
+    program: WebGlProgram,
+    vertex_shader: WebGlShader,
+    fragment_shader: WebGlShader,
+}
+
+/// Starts compilation and linking without waiting for the result.
+///
+/// Status isn't queried here, so with `KHR_parallel_shader_compile` the driver doesn't block.
+pub fn start_program(
+    gl: &GL,
+    vertex_shader_src: &str,
+    fragment_shader_src: &str,
+) -> Result<PendingProgram, ProgramError> {
+    let vertex_shader = start_shader(
+        gl,
+        GL::VERTEX_SHADER,
+        vertex_shader_src,
+        ProgramStage::VertexShader,
+    )?;
+    let fragment_shader = match start_shader(
+        gl,
+        GL::FRAGMENT_SHADER,
+        fragment_shader_src,
+        ProgramStage::FragmentShader,
+    ) {
+        Ok(shader) => shader,
+        Err(error) => {
+            gl.delete_shader(Some(&vertex_shader));
+            return Err(error);
+        }
+    };
+
+    let Some(program) = gl.create_program() else {
+        gl.delete_shader(Some(&vertex_shader));
+        gl.delete_shader(Some(&fragment_shader));
+        return Err(ProgramError {
+            stage: ProgramStage::Link,
+            log: "Failed to create program".to_owned(),
+        });
+    };
+    gl.attach_shader(&program, &vertex_shader);
+    gl.attach_shader(&program, &fragment_shader);
+    gl.link_program(&program);
+    Ok(PendingProgram {
+        program,
+        vertex_shader,
+        fragment_shader,
+    })
+}
+
+impl PendingProgram {
+    /// Result can be taken without blocking, always `true` if `parallel` extension is not enabled.
+    pub fn is_ready(&self, gl: &GL, parallel: bool) -> bool {
+        !parallel
+            || gl
+                .get_program_parameter(&self.program, COMPLETION_STATUS_KHR)
+                .as_bool()
+                .unwrap_or(true)
+    }
+
+    /// Checks result of linking, error has info log of the stage which failed first.
+    pub fn finish(self, gl: &GL) -> Result<WebGlProgram, ProgramError> {
+        let Self {
+            program,
+            vertex_shader,
+            fragment_shader,
+        } = self;
+        let linked = gl
+            .get_program_parameter(&program, GL::LINK_STATUS)
+            .as_bool()
+            .unwrap_or(false);
+        let result = if linked {
+            Ok(program)
+        } else {
+            let error = shader_error(gl, &vertex_shader, ProgramStage::VertexShader)
+                .or_else(|| shader_error(gl, &fragment_shader, ProgramStage::FragmentShader))
+                .unwrap_or_else(|| ProgramError {
+                    stage: ProgramStage::Link,
+                    log: gl.get_program_info_log(&program).unwrap_or_default(),
+                });
+            gl.delete_program(Some(&program));
+            Err(error)
+        };
+        // Shaders are freed together with the program
+        gl.delete_shader(Some(&vertex_shader));
+        gl.delete_shader(Some(&fragment_shader));
+        result
+    }
+
+    /// Frees program, which is no longer needed, without waiting for the driver.
+    pub fn delete(self, gl: &GL) {
+        gl.delete_program(Some(&self.program));
+        gl.delete_shader(Some(&self.vertex_shader));
+        gl.delete_shader(Some(&self.fragment_shader));
+    }
+}
+
+/// Compiles and links program from sources of vertex and fragment shaders, blocking till done.
+pub fn compile_program(
+    gl: &GL,
+    vertex_shader_src: &str,
+    fragment_shader_src: &str,
+) -> Result<WebGlProgram, ProgramError> {
+    start_program(gl, vertex_shader_src, fragment_shader_src)?.finish(gl)
+}
@@ -0,0 +1,110 @@
+//! Description of a multipass project as it comes from JS.
+
+use core::fmt;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Number of `iChannelN` samplers available in every pass.
+pub const CHANNEL_COUNT: usize = 4;
+
+/// Identifier of an offscreen buffer pass, buffers are rendered in alphabetical order before the image pass.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BufferId {
+    A,
+    B,
+    C,
+    D,
+}
+
+impl fmt::Display for BufferId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Buffer {self:?}")
+    }
+}
+
+/// Source of an `iChannelN` sampler.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelInput {
+    /// Latest output of a buffer, which is the previous frame if the buffer reads itself
+    Buffer(BufferId),
+    /// Image set by `set_channel_texture` into the slot
+    Texture(usize),
+    /// Keyboard state in Shadertoy layout
+    Keyboard,
+}
+
+/// Code of a single pass with its channel bindings.
+#[derive(Clone, Debug, Deserialize, Default)]
+pub struct PassSource {
+    pub code: String,
+    #[serde(default)]
+    pub channels: Vec<Option<ChannelInput>>,
+}
+
+impl PassSource {
+    /// Binding of `iChannel{index}`.
+    pub fn channel(&self, index: usize) -> Option<ChannelInput> {
+        self.channels.get(index).copied().flatten()
+    }
+}
+
+/// Set of passes rendered every frame, similar to tabs of a shadertoy.
+#[derive(Clone, Debug, Deserialize, Default)]
+pub struct Project {
+    /// Code prepended to every pass
+    #[serde(default)]
+    pub common: String,
+    #[serde(default)]
+    pub buffers: BTreeMap<BufferId, PassSource>,
+    pub image: PassSource,
+}
+
+impl Project {
+    /// Project with a single image pass, which is what `set_fragment_shader` sets.
+    ///
+    /// `iChannelN` of the pass reads texture slot `N`.
+    pub fn from_image(code: &str) -> Self {
+        Self {
+            image: PassSource {
+                code: code.to_owned(),
+                channels: (0..CHANNEL_COUNT)
+                    .map(|slot| Some(ChannelInput::Texture(slot)))
+                    .collect(),
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Checks references between passes, returns description of the first problem.
+    pub fn validate(&self) -> Result<(), String> {
+        let passes = self
+            .buffers
+            .iter()
+            .map(|(id, pass)| (id.to_string(), pass))
+            .chain(core::iter::once(("Image".to_owned(), &self.image)));
+        for (name, pass) in passes {
+            if pass.channels.len() > CHANNEL_COUNT {
+                return Err(format!(
+                    "{name} has {} channels, but only {CHANNEL_COUNT} are supported",
+                    pass.channels.len()
+                ));
+            }
+            for input in pass.channels.iter().flatten() {
+                match input {
+                    ChannelInput::Buffer(id) if !self.buffers.contains_key(id) => {
+                        return Err(format!("{name} reads {id}, which is not defined"));
+                    }
+                    ChannelInput::Texture(slot) if *slot >= CHANNEL_COUNT => {
+                        return Err(format!(
+                            "{name} reads texture {slot}, but only {CHANNEL_COUNT} slots are supported"
+                        ));
+                    }
+                    ChannelInput::Buffer(_) | ChannelInput::Texture(_) | ChannelInput::Keyboard => {
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
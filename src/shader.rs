@@ -0,0 +1,513 @@
+//! Preparation of user fragment shader code.
+//!
+//! User code comes in one of two dialects: native Shadertoy code (`mainImage`, `iTime`, ...)
+//! or the runner's own dialect (`render_image`, `u_time`, ...). Both are detected
+//! independently, so a `mainImage` which uses `u_*` uniforms is accepted as well.
+
+use crate::include::{Chunk, Includes, ShaderLibrary};
+use crate::params::{parse_param, ShaderParam};
+use serde::Serialize;
+
+/// Function which is called from generated `main()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryPoint {
+    /// `void mainImage(out vec4 fragColor, in vec2 fragCoord)`, as on shadertoy.com
+    MainImage,
+    /// `void render_image(out vec4 fragColor, in vec2 fragCoord)`
+    RenderImage,
+}
+
+impl EntryPoint {
+    fn function_name(self) -> &'static str {
+        match self {
+            EntryPoint::MainImage => "mainImage",
+            EntryPoint::RenderImage => "render_image",
+        }
+    }
+}
+
+/// Naming of built-in uniforms.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UniformNaming {
+    /// `iResolution`, `iTime`, ...
+    Shadertoy,
+    /// `u_resolution`, `u_time`, ...
+    Native,
+}
+
+/// Names of built-in uniforms in one of dialects.
+#[derive(Debug)]
+pub struct UniformNames {
+    pub resolution: &'static str,
+    pub time: &'static str,
+    pub time_delta: &'static str,
+    pub frame: &'static str,
+    pub frame_rate: &'static str,
+    pub mouse: &'static str,
+    pub date: &'static str,
+}
+
+impl UniformNames {
+    fn all(&self) -> [&'static str; 7] {
+        [
+            self.resolution,
+            self.time,
+            self.time_delta,
+            self.frame,
+            self.frame_rate,
+            self.mouse,
+            self.date,
+        ]
+    }
+}
+
+const SHADERTOY_UNIFORM_NAMES: UniformNames = UniformNames {
+    resolution: "iResolution",
+    time: "iTime",
+    time_delta: "iTimeDelta",
+    frame: "iFrame",
+    frame_rate: "iFrameRate",
+    mouse: "iMouse",
+    date: "iDate",
+};
+
+const NATIVE_UNIFORM_NAMES: UniformNames = UniformNames {
+    resolution: "u_resolution",
+    time: "u_time",
+    time_delta: "u_time_delta",
+    frame: "u_frame",
+    frame_rate: "u_frame_rate",
+    mouse: "u_mouse",
+    date: "u_date",
+};
+
+/// Names of `iChannelN` samplers, which are the same in both dialects.
+pub const CHANNEL_NAMES: [&str; 4] = ["iChannel0", "iChannel1", "iChannel2", "iChannel3"];
+
+/// Name of `vec3[4]` with sizes of channel inputs, the same in both dialects.
+pub const CHANNEL_RESOLUTION_NAME: &str = "iChannelResolution";
+
+/// Name of `vec4[4]` with pressed pointers, the same in both dialects.
+pub const TOUCH_NAME: &str = "iTouch";
+
+impl UniformNaming {
+    pub fn names(self) -> &'static UniformNames {
+        match self {
+            UniformNaming::Shadertoy => &SHADERTOY_UNIFORM_NAMES,
+            UniformNaming::Native => &NATIVE_UNIFORM_NAMES,
+        }
+    }
+}
+
+/// Dialect of user code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dialect {
+    pub entry_point: EntryPoint,
+    pub naming: UniformNaming,
+}
+
+impl Dialect {
+    /// Detects dialect by identifiers used in code, comments are ignored.
+    ///
+    /// Native naming wins if any of `u_*` uniforms is used, because shaders ported by hand
+    /// may still have `iTime` as a name of a local variable or a function parameter.
+    pub fn detect(code: &str) -> Self {
+        let mut has_main_image = false;
+        let mut has_shadertoy_uniforms = false;
+        let mut has_native_uniforms = false;
+        for identifier in identifiers(code) {
+            if identifier == EntryPoint::MainImage.function_name() {
+                has_main_image = true;
+            } else if SHADERTOY_UNIFORM_NAMES.all().contains(&identifier) {
+                has_shadertoy_uniforms = true;
+            } else if NATIVE_UNIFORM_NAMES.all().contains(&identifier) {
+                has_native_uniforms = true;
+            }
+        }
+
+        let entry_point = if has_main_image {
+            EntryPoint::MainImage
+        } else {
+            EntryPoint::RenderImage
+        };
+        let naming = if !has_native_uniforms && (has_shadertoy_uniforms || has_main_image) {
+            UniformNaming::Shadertoy
+        } else {
+            UniformNaming::Native
+        };
+
+        Self {
+            entry_point,
+            naming,
+        }
+    }
+}
+
+/// Kind of a pass, which the code is prepared for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PassKind {
+    /// Renders to the canvas
+    Image,
+    /// Renders to an offscreen float buffer
+    Buffer,
+}
+
+/// Part of prepared source, which user code came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceOrigin {
+    /// Prelude or `main()` added by `prepare_shader`
+    Generated,
+    /// Code shared between passes of a project
+    Common,
+    /// Code of the pass itself
+    User,
+    /// Module pulled in by `#include`
+    Module,
+}
+
+/// Range of lines of prepared source, which came from a single origin.
+#[derive(Clone, Debug)]
+struct Segment {
+    /// Line of prepared source, 1-based as in GLSL info logs
+    first_line: u32,
+    line_count: u32,
+    origin: SourceOrigin,
+    /// Name of the module for `SourceOrigin::Module`
+    module: Option<String>,
+    /// Line of the origin, which the segment starts at
+    origin_line: u32,
+}
+
+/// Line of code, which a line of prepared source came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceLocation<'a> {
+    pub origin: SourceOrigin,
+    pub module: Option<&'a str>,
+    /// 1-based line within the origin
+    pub line: u32,
+}
+
+/// Maps lines of prepared source back to code they came from.
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    segments: Vec<Segment>,
+}
+
+impl SourceMap {
+    /// Returns origin of a line of prepared source and the line number within the origin.
+    pub fn map(&self, line: u32) -> Option<SourceLocation<'_>> {
+        self.segments.iter().find_map(|segment| {
+            (segment.first_line..segment.first_line + segment.line_count)
+                .contains(&line)
+                .then(|| SourceLocation {
+                    origin: segment.origin,
+                    module: segment.module.as_deref(),
+                    line: segment.origin_line + line - segment.first_line,
+                })
+        })
+    }
+}
+
+/// Source assembled from pieces of different origin, each piece starts on a new line.
+#[derive(Default)]
+struct SourceBuilder {
+    source: String,
+    map: SourceMap,
+}
+
+impl SourceBuilder {
+    fn push(&mut self, origin: SourceOrigin, text: &str) {
+        self.push_at(origin, None, 1, text);
+    }
+
+    /// Pushes code of `origin` or of an included module, if the chunk came from one.
+    fn push_chunk(&mut self, origin: SourceOrigin, chunk: &Chunk<'_>) {
+        let origin = if chunk.module.is_some() {
+            SourceOrigin::Module
+        } else {
+            origin
+        };
+        self.push_at(origin, chunk.module, chunk.first_line, &chunk.text);
+    }
+
+    fn push_at(
+        &mut self,
+        origin: SourceOrigin,
+        module: Option<&str>,
+        origin_line: u32,
+        text: &str,
+    ) {
+        let first_line = self
+            .map
+            .segments
+            .last()
+            .map_or(1, |segment| segment.first_line + segment.line_count);
+        let line_count = text.matches('\n').count() as u32 + 1;
+        self.map.segments.push(Segment {
+            first_line,
+            line_count,
+            origin,
+            module: module.map(str::to_owned),
+            origin_line,
+        });
+        self.source.push_str(text);
+        self.source.push('\n');
+    }
+}
+
+/// User code wrapped with prelude and `main()`, ready for compilation.
+#[derive(Clone, Debug)]
+pub struct PreparedShader {
+    pub source: String,
+    pub dialect: Dialect,
+    pub source_map: SourceMap,
+    /// Custom uniforms declared by annotations or `#pragma param`
+    pub params: Vec<ShaderParam>,
+}
+
+/// Collects parameters declared in `chunks`, the first declaration of a name wins.
+///
+/// `#pragma param` lines are commented out and their uniforms are added to `declarations`.
+fn collect_params(
+    chunks: &mut [Chunk<'_>],
+    params: &mut Vec<ShaderParam>,
+    declarations: &mut String,
+) {
+    for chunk in chunks {
+        let mut text = String::with_capacity(chunk.text.len());
+        for (index, line) in chunk.text.split('\n').enumerate() {
+            if index > 0 {
+                text.push('\n');
+            }
+            let Some(parsed) = parse_param(line) else {
+                text.push_str(line);
+                continue;
+            };
+            if parsed.pragma {
+                // Keeps line count, so the source map stays valid
+                text.push_str("// ");
+            }
+            text.push_str(line);
+            if params.iter().any(|param| param.name == parsed.param.name) {
+                continue;
+            }
+            if parsed.pragma {
+                declarations.push_str(&format!(
+                    "\nuniform {}\t{};",
+                    parsed.param.param_type.glsl_name(),
+                    parsed.param.name
+                ));
+            }
+            params.push(parsed.param);
+        }
+        chunk.text = text;
+    }
+}
+
+/// Wraps user code with declarations of built-in uniforms and `main()` according to detected dialect.
+///
+/// `common` is code shared between all passes of a project, it is placed before `user_code`.
+/// Both of them may include modules of `library`.
+pub fn prepare_shader(
+    common: &str,
+    user_code: &str,
+    kind: PassKind,
+    library: &ShaderLibrary,
+) -> PreparedShader {
+    let mut includes = Includes::new(library);
+    let mut common = includes.expand(common);
+    let mut user_code = includes.expand(user_code);
+    let mut params = Vec::new();
+    let mut declarations = String::new();
+    collect_params(&mut common, &mut params, &mut declarations);
+    collect_params(&mut user_code, &mut params, &mut declarations);
+    let expanded = common
+        .iter()
+        .chain(&user_code)
+        .map(|chunk| chunk.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let dialect = Dialect::detect(&expanded);
+    let UniformNames {
+        resolution,
+        time,
+        time_delta,
+        frame,
+        frame_rate,
+        mouse,
+        date,
+    } = dialect.naming.names();
+    let entry_point = dialect.entry_point.function_name();
+    // Shadertoy ignores alpha of the image pass, so shaders from there often leave it undefined
+    let fix_alpha = if kind == PassKind::Image && dialect.naming == UniformNaming::Shadertoy {
+        "\n    frag_color.a = 1.0;"
+    } else {
+        ""
+    };
+
+    let prelude = format!("#version 300 es
+precision highp float;
+precision highp int;
+
+uniform vec3	{resolution}; // image/buffer	The viewport resolution (z is pixel aspect ratio, usually 1.0)
+uniform float	{time}; // image/sound/buffer	Current time in seconds
+uniform float	{time_delta}; // image/buffer	Time it takes to render a frame, in seconds
+uniform int	{frame}; // image/buffer	Current frame
+uniform float	{frame_rate}; // image/buffer	Number of frames rendered per second
+uniform vec4	{mouse}; // image/buffer	xy = current pixel coords (if LMB is down). zw = click pixel
+uniform vec4	{date}; // image/buffer/sound	Year, month, day, time in seconds in .xyzw
+uniform sampler2D	iChannel0; // image/buffer	Input channel, see `ChannelInput`
+uniform sampler2D	iChannel1; // image/buffer	Input channel, see `ChannelInput`
+uniform sampler2D	iChannel2; // image/buffer	Input channel, see `ChannelInput`
+uniform sampler2D	iChannel3; // image/buffer	Input channel, see `ChannelInput`
+uniform vec3	iChannelResolution[4]; // image/buffer	Resolution of input channels in pixels, zero if channel is empty
+uniform vec4	iTouch[4]; // image/buffer	xy = current pixel coords of pressed pointers, zw = press pixel, zero if slot is free{declarations}");
+    let epilogue = format!(
+        "in vec2 vUv;
+out vec4 frag_color;
+
+void main() {{
+    {entry_point}(frag_color, vUv * {resolution}.xy);{fix_alpha}
+}}"
+    );
+
+    let mut builder = SourceBuilder::default();
+    builder.push(SourceOrigin::Generated, &prelude);
+    for chunk in &common {
+        builder.push_chunk(SourceOrigin::Common, chunk);
+    }
+    for chunk in &user_code {
+        builder.push_chunk(SourceOrigin::User, chunk);
+    }
+    builder.push(SourceOrigin::Generated, &epilogue);
+
+    PreparedShader {
+        source: builder.source,
+        dialect,
+        source_map: builder.map,
+        params,
+    }
+}
+
+/// Iterates over identifiers of GLSL code, skipping comments.
+fn identifiers(code: &str) -> impl Iterator<Item = &str> {
+    let bytes = code.as_bytes();
+    let mut position = 0;
+    core::iter::from_fn(move || {
+        while position < bytes.len() {
+            let rest = &bytes[position..];
+            if rest.starts_with(b"//") {
+                position += rest
+                    .iter()
+                    .position(|&byte| byte == b'\n')
+                    .unwrap_or(rest.len());
+            } else if rest.starts_with(b"/*") {
+                position += rest
+                    .windows(2)
+                    .position(|window| window == b"*/")
+                    .map_or(rest.len(), |end| end + 2);
+            } else if rest[0].is_ascii_alphabetic() || rest[0] == b'_' {
+                let start = position;
+                position += rest
+                    .iter()
+                    .position(|&byte| !(byte.is_ascii_alphanumeric() || byte == b'_'))
+                    .unwrap_or(rest.len());
+                return Some(&code[start..position]);
+            } else if rest[0].is_ascii_digit() {
+                // Skip numeric literals together with suffixes like `1.0f` or `0x1Fu`
+                position += rest
+                    .iter()
+                    .position(|&byte| !(byte.is_ascii_alphanumeric() || byte == b'.'))
+                    .unwrap_or(rest.len());
+            } else {
+                position += 1;
+            }
+        }
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(code: &str) -> (EntryPoint, UniformNaming) {
+        let dialect = Dialect::detect(code);
+        (dialect.entry_point, dialect.naming)
+    }
+
+    #[test]
+    fn shadertoy_code() {
+        let code = "void mainImage(out vec4 fragColor, in vec2 fragCoord) {\n\
+            fragColor = vec4(fragCoord / iResolution.xy, sin(iTime), 1.0);\n}";
+        assert_eq!(
+            detect(code),
+            (EntryPoint::MainImage, UniformNaming::Shadertoy)
+        );
+        // Shadertoy entry point alone is enough
+        assert_eq!(
+            detect("void mainImage(out vec4 c, in vec2 p) { c = vec4(1.0); }"),
+            (EntryPoint::MainImage, UniformNaming::Shadertoy)
+        );
+    }
+
+    #[test]
+    fn native_code() {
+        let code = "void render_image(out vec4 color, in vec2 coord) {\n\
+            color = vec4(coord / u_resolution.xy, sin(u_time), 1.0);\n}";
+        assert_eq!(
+            detect(code),
+            (EntryPoint::RenderImage, UniformNaming::Native)
+        );
+        assert_eq!(
+            detect("void render_image(out vec4 c, in vec2 p) { c = vec4(1.0); }"),
+            (EntryPoint::RenderImage, UniformNaming::Native)
+        );
+    }
+
+    #[test]
+    fn mixed_code() {
+        // Native uniforms win over Shadertoy names used for locals
+        let code =
+            "void mainImage(out vec4 c, in vec2 p) { float iTime = u_time; c = vec4(iTime); }";
+        assert_eq!(detect(code), (EntryPoint::MainImage, UniformNaming::Native));
+        let code = "void render_image(out vec4 c, in vec2 p) { c = vec4(sin(iTime)); }";
+        assert_eq!(
+            detect(code),
+            (EntryPoint::RenderImage, UniformNaming::Shadertoy)
+        );
+    }
+
+    #[test]
+    fn comments_and_similar_names_are_ignored() {
+        let code = "// Port of mainImage, uses iTime\n\
+            /* void mainImage(out vec4 c, in vec2 p) { c = vec4(iMouse); } */\n\
+            float iTimeScale = 1.0f;\n\
+            void render_image(out vec4 c, in vec2 p) { c = vec4(iTimeScale); }";
+        assert_eq!(
+            detect(code),
+            (EntryPoint::RenderImage, UniformNaming::Native)
+        );
+    }
+
+    #[test]
+    fn alpha_is_fixed_only_for_shadertoy_image() {
+        let library = ShaderLibrary::default();
+        let code = "void mainImage(out vec4 c, in vec2 p) { c = vec4(iTime); }";
+        let image = prepare_shader("", code, PassKind::Image, &library);
+        assert!(image
+            .source
+            .contains("mainImage(frag_color, vUv * iResolution.xy);"));
+        assert!(image.source.contains("frag_color.a = 1.0;"));
+        let buffer = prepare_shader("", code, PassKind::Buffer, &library);
+        assert!(!buffer.source.contains("frag_color.a = 1.0;"));
+
+        let code = "void render_image(out vec4 c, in vec2 p) { c = vec4(u_time); }";
+        let native = prepare_shader("", code, PassKind::Image, &library);
+        assert!(native
+            .source
+            .contains("render_image(frag_color, vUv * u_resolution.xy);"));
+        assert!(!native.source.contains("frag_color.a = 1.0;"));
+    }
+}
@@ -0,0 +1,454 @@
+//! Playback parameters and uniform overrides set from JS.
+
+use crate::{
+    params::{ParamValue, ParamValues},
+    stats::GpuStats,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+pub struct ResolutionUniform {
+    pub width: f32,
+    pub height: f32,
+    pub pixel_aspect_ratio: f32,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default)]
+pub struct MouseUniform {
+    pub x: f32,
+    pub y: f32,
+    pub down_x: f32,
+    pub down_y: f32,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+pub struct DateUniform {
+    pub year: f32,
+    pub month: f32,
+    pub day: f32,
+    pub time: f32,
+}
+
+#[derive(Clone, Copy, Serialize, Debug, Default)]
+pub struct Uniforms {
+    pub resolution: Option<ResolutionUniform>,
+    pub time: Option<f32>,
+    pub time_delta: Option<f32>,
+    pub frame: Option<f32>,
+    pub frame_rate: Option<f32>,
+    pub mouse: Option<MouseUniform>,
+    pub date: Option<DateUniform>,
+}
+
+/// When frames are rendered during playback.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaybackMode {
+    /// `OnDemand` if shader doesn't use time and has no feedback buffers, otherwise `Continuous`
+    #[default]
+    Auto,
+    /// Every animation frame
+    Continuous,
+    /// Only when input changes: mouse, keys, size, state, shader or textures
+    OnDemand,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default)]
+pub struct Playback {
+    pub paused: Option<bool>,
+    pub speed: Option<f32>,
+    pub mode: Option<PlaybackMode>,
+}
+
+/// How pointer input is converted to `iMouse`.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseMode {
+    /// Drawing buffer pixels with origin at the bottom left, `zw` signs tell button and click state
+    #[default]
+    Shadertoy,
+    /// CSS pixels with origin at the top left, `zw` is always the press position
+    Legacy,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default)]
+pub struct Input {
+    pub mouse_mode: Option<MouseMode>,
+    /// Disables scroll and zoom by touches over the canvas, so they drive `iMouse` and `iTouch`,
+    /// `true` by default; `false` lets previews in scrollable pages pass swipes to the page
+    pub capture_touch: Option<bool>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default)]
+pub struct Render {
+    /// Size of drawing buffer relative to CSS size of canvas multiplied by `devicePixelRatio`
+    pub scale: Option<f32>,
+    /// Frame rate held by adaptive resolution, which is disabled if not set or zero
+    pub target_fps: Option<f32>,
+    /// Bounds of adaptive scale relative to drawing buffer size
+    pub min_scale: Option<f32>,
+    pub max_scale: Option<f32>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default)]
+pub struct Stats {
+    /// Measures GPU time of frames and emits `FrameStatsEvent`
+    pub gpu_timing: Option<bool>,
+}
+
+#[derive(Clone, Serialize, Debug, Default)]
+pub struct PlayerState {
+    pub playback: Option<Playback>,
+    pub uniforms: Option<Uniforms>,
+    pub input: Option<Input>,
+    pub render: Option<Render>,
+    pub stats: Option<Stats>,
+    /// Values of parameters declared by the shader, see `get_shader_params()`
+    pub custom: Option<ParamValues>,
+}
+
+/// Reply of `get_player_state()`, values are the ones of the last rendered frame.
+#[derive(Clone, Serialize, Debug)]
+pub struct PlayerStateSnapshot {
+    pub paused: bool,
+    pub speed: f32,
+    pub time: f32,
+    pub time_delta: f32,
+    pub frame: i32,
+    pub frame_rate: f32,
+    /// Measured rate of rendered frames
+    pub fps: f32,
+    /// Frames are rendered only when input changes
+    pub on_demand: bool,
+    /// GPU time of the latest frames, `None` if not measured
+    pub gpu: Option<GpuStats>,
+    pub resolution: ResolutionUniform,
+    /// Render size relative to CSS size of canvas multiplied by `devicePixelRatio`,
+    /// includes adjustment of adaptive resolution
+    pub render_scale: f32,
+    /// Overrides set by `update_player_state()`
+    pub state: PlayerState,
+}
+
+/// Field of an update, `None` if missing and `Some(None)` if set to `null`.
+type Update<T> = Option<Option<T>>;
+
+/// Wraps present values, so `null` is distinguished from missing field.
+fn present<'de, D, T>(deserializer: D) -> Result<Update<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+fn apply<T>(value: &mut Option<T>, update: Update<T>) {
+    if let Some(update) = update {
+        *value = update;
+    }
+}
+
+/// Uniform overrides received from JS, `null` returns uniform to automatic computation.
+#[derive(Clone, Copy, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct UniformsUpdate {
+    #[serde(deserialize_with = "present")]
+    pub resolution: Update<ResolutionUniform>,
+    #[serde(deserialize_with = "present")]
+    pub time: Update<f32>,
+    #[serde(deserialize_with = "present")]
+    pub time_delta: Update<f32>,
+    #[serde(deserialize_with = "present")]
+    pub frame: Update<f32>,
+    #[serde(deserialize_with = "present")]
+    pub frame_rate: Update<f32>,
+    #[serde(deserialize_with = "present")]
+    pub mouse: Update<MouseUniform>,
+    #[serde(deserialize_with = "present")]
+    pub date: Update<DateUniform>,
+}
+
+/// Argument of `update_player_state()`, `uniforms: null` resets all overrides.
+///
+/// `custom: null` returns all parameters to their defaults, `null` of a parameter resets only it.
+#[derive(Clone, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct PlayerStateUpdate {
+    pub playback: Option<Playback>,
+    #[serde(deserialize_with = "present")]
+    pub uniforms: Update<UniformsUpdate>,
+    pub input: Option<Input>,
+    pub render: Option<Render>,
+    pub stats: Option<Stats>,
+    #[serde(deserialize_with = "present")]
+    pub custom: Update<BTreeMap<String, Option<ParamValue>>>,
+}
+
+impl PlayerStateUpdate {
+    /// Checks values, which are not limited by their types.
+    pub fn validate(&self) -> Result<(), String> {
+        let custom = self.custom.iter().flatten().flatten();
+        for (name, value) in custom {
+            if let Some(value) = value {
+                value
+                    .validate()
+                    .map_err(|error| format!("{name}: {error}"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PlayerState {
+    /// Applies state received from JS, fields missing in `update` keep their values.
+    pub fn merge(&mut self, update: PlayerStateUpdate) {
+        match update.uniforms {
+            Some(Some(new_uniforms)) => {
+                let uniforms = self.uniforms.get_or_insert_with(Uniforms::default);
+                apply(&mut uniforms.resolution, new_uniforms.resolution);
+                apply(&mut uniforms.time, new_uniforms.time);
+                apply(&mut uniforms.time_delta, new_uniforms.time_delta);
+                apply(&mut uniforms.frame, new_uniforms.frame);
+                apply(&mut uniforms.frame_rate, new_uniforms.frame_rate);
+                apply(&mut uniforms.mouse, new_uniforms.mouse);
+                apply(&mut uniforms.date, new_uniforms.date);
+            }
+            Some(None) => self.uniforms = None,
+            None => {}
+        }
+
+        match update.custom {
+            Some(Some(new_custom)) => {
+                let custom = self.custom.get_or_insert_with(ParamValues::new);
+                for (name, value) in new_custom {
+                    match value {
+                        Some(value) => custom.insert(name, value),
+                        None => custom.remove(&name),
+                    };
+                }
+            }
+            Some(None) => self.custom = None,
+            None => {}
+        }
+
+        if let Some(playback) = &mut self.playback {
+            if let Some(new_playback) = update.playback {
+                playback.paused = new_playback.paused.or(playback.paused);
+                playback.speed = new_playback.speed.or(playback.speed);
+                playback.mode = new_playback.mode.or(playback.mode);
+            }
+        } else {
+            self.playback = update.playback;
+        }
+
+        if let Some(input) = &mut self.input {
+            if let Some(new_input) = update.input {
+                input.mouse_mode = new_input.mouse_mode.or(input.mouse_mode);
+                input.capture_touch = new_input.capture_touch.or(input.capture_touch);
+            }
+        } else {
+            self.input = update.input;
+        }
+
+        if let Some(render) = &mut self.render {
+            if let Some(new_render) = update.render {
+                render.scale = new_render.scale.or(render.scale);
+                render.target_fps = new_render.target_fps.or(render.target_fps);
+                render.min_scale = new_render.min_scale.or(render.min_scale);
+                render.max_scale = new_render.max_scale.or(render.max_scale);
+            }
+        } else {
+            self.render = update.render;
+        }
+
+        if let Some(stats) = &mut self.stats {
+            if let Some(new_stats) = update.stats {
+                stats.gpu_timing = new_stats.gpu_timing.or(stats.gpu_timing);
+            }
+        } else {
+            self.stats = update.stats;
+        }
+    }
+
+    pub fn gpu_timing(&self) -> bool {
+        self.stats
+            .and_then(|stats| stats.gpu_timing)
+            .unwrap_or(false)
+    }
+
+    pub fn paused(&self) -> bool {
+        matches!(
+            self.playback,
+            Some(Playback {
+                paused: Some(true),
+                ..
+            })
+        )
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.playback
+            .and_then(|playback| playback.speed)
+            .unwrap_or(1.0)
+    }
+
+    pub fn playback_mode(&self) -> PlaybackMode {
+        self.playback
+            .and_then(|playback| playback.mode)
+            .unwrap_or_default()
+    }
+
+    pub fn capture_touch(&self) -> bool {
+        self.input
+            .and_then(|input| input.capture_touch)
+            .unwrap_or(true)
+    }
+
+    pub fn mouse_mode(&self) -> MouseMode {
+        self.input
+            .and_then(|input| input.mouse_mode)
+            .unwrap_or_default()
+    }
+
+    /// Render scale, invalid values are replaced with 1.0.
+    pub fn render_scale(&self) -> f32 {
+        self.render
+            .and_then(|render| render.scale)
+            .filter(|scale| scale.is_finite() && *scale > 0.0)
+            .unwrap_or(1.0)
+    }
+
+    pub fn set_paused(&mut self, value: bool) {
+        if let Some(playback) = &mut self.playback {
+            playback.paused = Some(value);
+        } else {
+            self.playback = Some(Playback {
+                paused: Some(value),
+                ..Default::default()
+            });
+        }
+    }
+
+    pub fn update_mouse(
+        &mut self,
+        update: impl FnOnce(Option<MouseUniform>) -> Option<MouseUniform>,
+    ) {
+        if let Some(uniforms) = &mut self.uniforms {
+            uniforms.mouse = update(uniforms.mouse);
+        } else {
+            self.uniforms = Some(Uniforms {
+                mouse: update(None),
+                ..Default::default()
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(state: &mut PlayerState, json: &str) {
+        let update: PlayerStateUpdate = serde_json::from_str(json).expect("valid update");
+        state.merge(update);
+    }
+
+    #[test]
+    fn uniform_overrides() {
+        let mut state = PlayerState::default();
+        merged(
+            &mut state,
+            r#"{ "uniforms": { "time": 1.5, "frame": 10 } }"#,
+        );
+        let uniforms = state.uniforms.unwrap();
+        assert_eq!((uniforms.time, uniforms.frame), (Some(1.5), Some(10.0)));
+
+        // Missing fields keep their overrides, `null` resets only its field
+        merged(
+            &mut state,
+            r#"{ "uniforms": { "time": null, "frame_rate": 30 } }"#,
+        );
+        let uniforms = state.uniforms.unwrap();
+        assert_eq!(uniforms.time, None);
+        assert_eq!(uniforms.frame, Some(10.0));
+        assert_eq!(uniforms.frame_rate, Some(30.0));
+
+        merged(&mut state, r#"{ "playback": { "speed": 2 } }"#);
+        assert!(state.uniforms.is_some());
+
+        merged(&mut state, r#"{ "uniforms": null }"#);
+        assert!(state.uniforms.is_none());
+    }
+
+    #[test]
+    fn custom_values() {
+        let mut state = PlayerState::default();
+        merged(
+            &mut state,
+            r##"{ "custom": { "u_gain": 0.5, "u_tint": "#ff0000" } }"##,
+        );
+        merged(
+            &mut state,
+            r#"{ "custom": { "u_gain": null, "u_on": true } }"#,
+        );
+        let custom = state.custom.as_ref().unwrap();
+        assert_eq!(custom.get("u_gain"), None);
+        assert_eq!(custom["u_tint"], ParamValue::Color("#ff0000".to_owned()));
+        assert_eq!(custom["u_on"], ParamValue::Bool(true));
+
+        merged(&mut state, r#"{ "stats": { "gpu_timing": true } }"#);
+        assert_eq!(state.custom.as_ref().map(ParamValues::len), Some(2));
+
+        merged(&mut state, r#"{ "custom": null }"#);
+        assert!(state.custom.is_none());
+    }
+
+    #[test]
+    fn missing_fields_keep_values() {
+        let mut state = PlayerState::default();
+        merged(
+            &mut state,
+            r#"{ "playback": { "paused": true, "speed": 2 }, "input": { "capture_touch": false } }"#,
+        );
+        merged(
+            &mut state,
+            r#"{ "playback": { "mode": "on_demand" }, "input": { "mouse_mode": "legacy" } }"#,
+        );
+        assert!(state.paused());
+        assert_eq!(state.speed(), 2.0);
+        assert_eq!(state.playback_mode(), PlaybackMode::OnDemand);
+        assert!(!state.capture_touch());
+        assert_eq!(state.mouse_mode(), MouseMode::Legacy);
+
+        merged(
+            &mut state,
+            r#"{ "playback": { "paused": false }, "render": { "scale": 0.5 } }"#,
+        );
+        assert!(!state.paused());
+        assert_eq!(state.speed(), 2.0);
+        assert_eq!(state.render_scale(), 0.5);
+    }
+
+    #[test]
+    fn defaults_of_empty_state() {
+        let state = PlayerState::default();
+        assert!(!state.paused());
+        assert_eq!(state.speed(), 1.0);
+        assert_eq!(state.playback_mode(), PlaybackMode::Auto);
+        assert!(state.capture_touch());
+        assert!(!state.gpu_timing());
+        assert_eq!(state.render_scale(), 1.0);
+    }
+
+    #[test]
+    fn invalid_custom_value_is_rejected() {
+        let update: PlayerStateUpdate =
+            serde_json::from_str(r#"{ "custom": { "u_tint": "red", "u_gain": null } }"#).unwrap();
+        let error = update.validate().unwrap_err();
+        assert!(error.starts_with("u_tint: "), "{error}");
+
+        let update: PlayerStateUpdate = serde_json::from_str(r#"{ "custom": null }"#).unwrap();
+        assert!(update.validate().is_ok());
+    }
+}
@@ -0,0 +1,133 @@
+//! GPU time of frames measured with `EXT_disjoint_timer_query_webgl2`.
+//!
+//! Results of queries come a few frames later, so they are polled on every frame
+//! and kept in a rolling history.
+
+use minwebgl as gl;
+use serde::Serialize;
+use std::collections::VecDeque;
+use web_sys::{WebGl2RenderingContext as GL, WebGlQuery};
+
+const EXTENSION_NAME: &str = "EXT_disjoint_timer_query_webgl2";
+const TIME_ELAPSED_EXT: u32 = 0x88BF;
+const GPU_DISJOINT_EXT: u32 = 0x8FBB;
+/// Number of frames in the history
+const HISTORY_LENGTH: usize = 120;
+/// Frames are not measured while this many queries wait for results
+const MAX_PENDING_QUERIES: usize = 4;
+/// Interval of `FrameStatsEvent`, in seconds
+pub const STATS_EVENT_INTERVAL: f64 = 1.0;
+
+/// GPU time of frames in the history, in milliseconds.
+#[derive(Clone, Copy, Serialize, Debug)]
+pub struct GpuStats {
+    pub min_ms: f64,
+    pub avg_ms: f64,
+    pub max_ms: f64,
+    /// Number of measured frames
+    pub samples: usize,
+}
+
+/// Detail of `FrameStatsEvent`.
+#[derive(Clone, Copy, Serialize, Debug)]
+pub struct FrameStats {
+    /// Measured rate of rendered frames
+    pub fps: f64,
+    /// `None` if timer queries are not supported or nothing is measured yet
+    pub gpu: Option<GpuStats>,
+}
+
+/// Timer queries around frames, inert if the extension is not supported.
+pub struct GpuTimer {
+    supported: bool,
+    /// Ended queries, oldest first
+    pending: VecDeque<WebGlQuery>,
+    active: Option<WebGlQuery>,
+    /// GPU time of the latest frames in milliseconds
+    history: VecDeque<f64>,
+}
+
+impl GpuTimer {
+    pub fn new(gl: &GL) -> Self {
+        let supported = matches!(gl.get_extension(EXTENSION_NAME), Ok(Some(_)));
+        if !supported {
+            gl::info!("{EXTENSION_NAME} is not supported, GPU time is not measured");
+        }
+        Self {
+            supported,
+            pending: VecDeque::new(),
+            active: None,
+            history: VecDeque::with_capacity(HISTORY_LENGTH),
+        }
+    }
+
+    /// Starts measuring commands of a frame.
+    pub fn begin(&mut self, gl: &GL) {
+        if !self.supported || self.active.is_some() || self.pending.len() >= MAX_PENDING_QUERIES {
+            return;
+        }
+        if let Some(query) = gl.create_query() {
+            gl.begin_query(TIME_ELAPSED_EXT, &query);
+            self.active = Some(query);
+        }
+    }
+
+    pub fn end(&mut self, gl: &GL) {
+        if let Some(query) = self.active.take() {
+            gl.end_query(TIME_ELAPSED_EXT);
+            self.pending.push_back(query);
+        }
+    }
+
+    /// Collects results of finished queries.
+    pub fn poll(&mut self, gl: &GL) {
+        // Results are unreliable if GPU was interrupted, e.g. by power state change
+        let disjoint = gl
+            .get_parameter(GPU_DISJOINT_EXT)
+            .is_ok_and(|value| value.as_bool() == Some(true));
+        while let Some(query) = self.pending.front() {
+            let available = gl
+                .get_query_parameter(query, GL::QUERY_RESULT_AVAILABLE)
+                .as_bool()
+                .unwrap_or(false);
+            if !available && !disjoint {
+                break;
+            }
+            if !disjoint {
+                if let Some(nanoseconds) = gl.get_query_parameter(query, GL::QUERY_RESULT).as_f64()
+                {
+                    if self.history.len() == HISTORY_LENGTH {
+                        self.history.pop_front();
+                    }
+                    self.history.push_back(nanoseconds / 1e6);
+                }
+            }
+            gl.delete_query(self.pending.pop_front().as_ref());
+        }
+    }
+
+    pub fn stats(&self) -> Option<GpuStats> {
+        if self.history.is_empty() {
+            return None;
+        }
+        let (min_ms, max_ms, sum) = self.history.iter().fold(
+            (f64::INFINITY, f64::NEG_INFINITY, 0.0),
+            |(min, max, sum), &time| (min.min(time), max.max(time), sum + time),
+        );
+        Some(GpuStats {
+            min_ms,
+            avg_ms: sum / self.history.len() as f64,
+            max_ms,
+            samples: self.history.len(),
+        })
+    }
+
+    pub fn delete(mut self, gl: &GL) {
+        if self.active.is_some() {
+            gl.end_query(TIME_ELAPSED_EXT);
+        }
+        for query in self.active.take().into_iter().chain(self.pending.drain(..)) {
+            gl.delete_query(Some(&query));
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! Images supplied from JS and keyboard state for `iChannelN` samplers.
+
+use crate::{keyboard::Keyboard, project::CHANNEL_COUNT};
+use minwebgl as gl;
+use serde::Deserialize;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    Blob, ImageBitmap, ImageBitmapOptions, ImageOrientation, PremultiplyAlpha,
+    WebGl2RenderingContext as GL, WebGlTexture,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+    #[default]
+    Mipmap,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureWrap {
+    Clamp,
+    #[default]
+    Repeat,
+    Mirror,
+}
+
+/// Sampling options of a channel texture, defaults match Shadertoy.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct TextureOptions {
+    pub filter: TextureFilter,
+    pub wrap: TextureWrap,
+    /// Puts the first row of the image at the top of texture space
+    pub vflip: bool,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            filter: TextureFilter::default(),
+            wrap: TextureWrap::default(),
+            vflip: true,
+        }
+    }
+}
+
+/// Decodes `ImageBitmap`, `Blob` or bytes of an image file into a bitmap ready for upload.
+///
+/// WebGL ignores `UNPACK_FLIP_Y_WEBGL` for bitmaps, so flip is applied while decoding.
+pub async fn decode_image(source: JsValue, vflip: bool) -> Result<ImageBitmap, JsValue> {
+    let window = web_sys::window().ok_or_else(|| JsValue::from_str("No window"))?;
+    let options = ImageBitmapOptions::new();
+    options.set_image_orientation(if vflip {
+        ImageOrientation::FlipY
+    } else {
+        ImageOrientation::FromImage
+    });
+    options.set_premultiply_alpha(PremultiplyAlpha::None);
+
+    let promise = if let Some(bitmap) = source.dyn_ref::<ImageBitmap>() {
+        window.create_image_bitmap_with_image_bitmap_and_image_bitmap_options(bitmap, &options)?
+    } else if let Some(blob) = source.dyn_ref::<Blob>() {
+        window.create_image_bitmap_with_blob_and_image_bitmap_options(blob, &options)?
+    } else {
+        // `Uint8Array` or `ArrayBuffer` with content of PNG, JPEG, etc.
+        let blob = Blob::new_with_u8_array_sequence(&js_sys::Array::of1(&source))?;
+        window.create_image_bitmap_with_blob_and_image_bitmap_options(&blob, &options)?
+    };
+    Ok(JsFuture::from(promise).await?.unchecked_into())
+}
+
+/// Image of a channel, bitmap is kept to upload it again after context restore.
+pub struct ChannelTexture {
+    bitmap: ImageBitmap,
+    options: TextureOptions,
+    texture: Option<WebGlTexture>,
+}
+
+impl ChannelTexture {
+    pub fn new(bitmap: ImageBitmap, options: TextureOptions) -> Self {
+        Self {
+            bitmap,
+            options,
+            texture: None,
+        }
+    }
+
+    fn upload(&self, gl: &GL) -> Option<WebGlTexture> {
+        let texture = gl.create_texture()?;
+        gl.bind_texture(GL::TEXTURE_2D, Some(&texture));
+        if let Err(error) = gl.tex_image_2d_with_u32_and_u32_and_image_bitmap(
+            GL::TEXTURE_2D,
+            0,
+            GL::RGBA as i32,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            &self.bitmap,
+        ) {
+            gl::error!("Failed to upload channel texture: {error:?}");
+        }
+
+        let (min_filter, mag_filter) = match self.options.filter {
+            TextureFilter::Nearest => (GL::NEAREST, GL::NEAREST),
+            TextureFilter::Linear => (GL::LINEAR, GL::LINEAR),
+            TextureFilter::Mipmap => {
+                gl.generate_mipmap(GL::TEXTURE_2D);
+                (GL::LINEAR_MIPMAP_LINEAR, GL::LINEAR)
+            }
+        };
+        let wrap = match self.options.wrap {
+            TextureWrap::Clamp => GL::CLAMP_TO_EDGE,
+            TextureWrap::Repeat => GL::REPEAT,
+            TextureWrap::Mirror => GL::MIRRORED_REPEAT,
+        };
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, min_filter as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, mag_filter as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, wrap as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, wrap as i32);
+        gl.bind_texture(GL::TEXTURE_2D, None);
+
+        Some(texture)
+    }
+
+    fn resolution(&self) -> [f32; 3] {
+        [self.bitmap.width() as f32, self.bitmap.height() as f32, 1.0]
+    }
+}
+
+/// Channel textures set from JS and keyboard state, uploaded lazily on the next frame.
+#[derive(Default)]
+pub struct ChannelTextures {
+    slots: [Option<ChannelTexture>; CHANNEL_COUNT],
+    /// Textures of replaced slots, deleted on the next frame
+    retired: Vec<WebGlTexture>,
+    pub keyboard: Keyboard,
+}
+
+impl ChannelTextures {
+    pub fn set(&mut self, slot: usize, texture: ChannelTexture) {
+        if let Some(old) = self.slots[slot].replace(texture) {
+            old.bitmap.close();
+            self.retired.extend(old.texture);
+        }
+    }
+
+    /// Uploads new textures and deletes replaced ones.
+    pub fn prepare(&mut self, gl: &GL) {
+        for texture in &self.retired {
+            gl.delete_texture(Some(texture));
+        }
+        self.retired.clear();
+        for slot in self.slots.iter_mut().flatten() {
+            if slot.texture.is_none() {
+                slot.texture = slot.upload(gl);
+            }
+        }
+        self.keyboard.prepare(gl);
+    }
+
+    /// Forgets GL objects of the lost context, bitmaps are uploaded again by `prepare()`.
+    pub fn invalidate(&mut self) {
+        self.retired.clear();
+        for slot in self.slots.iter_mut().flatten() {
+            slot.texture = None;
+        }
+        self.keyboard.invalidate();
+    }
+
+    /// Uploaded texture of a slot and its `iChannelResolution`.
+    pub fn get(&self, slot: usize) -> Option<(&WebGlTexture, [f32; 3])> {
+        let slot = self.slots.get(slot)?.as_ref()?;
+        Some((slot.texture.as_ref()?, slot.resolution()))
+    }
+
+    /// Deletes all textures and closes bitmaps, slots are left empty.
+    pub fn delete(&mut self, gl: &GL) {
+        for texture in self.retired.drain(..) {
+            gl.delete_texture(Some(&texture));
+        }
+        for slot in self.slots.iter_mut() {
+            if let Some(slot) = slot.take() {
+                slot.bitmap.close();
+                gl.delete_texture(slot.texture.as_ref());
+            }
+        }
+        self.keyboard.delete(gl);
+    }
+}